@@ -0,0 +1,159 @@
+//! Why the AI likes a move: every legal move with its search score, the expected line of play
+//! and the heuristic components of the position it leads to
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use underflow_core::{
+    protocol::{FlowCommand, GamePhase},
+    server::FlowServer,
+};
+
+use crate::filling::FillingStrategy;
+use crate::hard::HardStrategy;
+use crate::search::{SearchAlgorithm, SearchBudget, SearchContext};
+use crate::{HeuristicBreakdown, HeuristicWeights, get_valid_commands, heuristic_breakdown};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis {
+    pub cmd: FlowCommand,
+    /// Search score for the analyzed player, comparable between the moves of one analysis only
+    pub score: f64,
+    /// Expected line of play, starting with this move
+    pub pv: Vec<FlowCommand>,
+    /// Heuristic of the position right after the move
+    pub breakdown: HeuristicBreakdown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub player: u8,
+    /// Heuristic of the position before any move
+    pub position: HeuristicBreakdown,
+    /// Best first, moves of equal score keep the order of [get_valid_commands]
+    pub moves: Vec<MoveAnalysis>,
+    /// Search depth the scores come from, 0 while filling where completions are sampled instead
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+impl Analysis {
+    pub fn best(&self) -> Option<&MoveAnalysis> {
+        self.moves.first()
+    }
+}
+
+/// Score every legal move of `player` as if it were their turn. Deepening stops at the budget
+/// like [HardStrategy], a budget spent before the first depth leaves every move at its heuristic.
+pub fn analyze(
+    server: &FlowServer,
+    player: u8,
+    budget: &SearchBudget,
+    algorithm: SearchAlgorithm,
+    weights: &HeuristicWeights,
+    seed: u64,
+) -> Analysis {
+    let mut root = server.fork();
+    root.current_player = player;
+    let search = SearchContext::new(player, budget, *weights);
+    let after = |cmd: FlowCommand| {
+        let mut next = root.fork();
+        next.handle(cmd).ok()?;
+        Some(heuristic_breakdown(&next, player, weights))
+    };
+
+    let mut depth = 0;
+    let mut moves: Vec<_> = if root.phase == GamePhase::Filling {
+        let mut rng = StdRng::seed_from_u64(seed);
+        get_valid_commands(&root, player)
+            .into_iter()
+            .filter_map(|cmd| {
+                let score = FillingStrategy::evaluate(&search, &root, cmd, rng.random());
+                Some(MoveAnalysis {
+                    cmd,
+                    score,
+                    pv: vec![cmd],
+                    breakdown: after(cmd)?,
+                })
+            })
+            .collect()
+    } else if let Some((searched, lines)) =
+        HardStrategy::deepen(&search, &root, budget.max_depth, algorithm)
+    {
+        depth = searched;
+        lines
+            .into_iter()
+            .filter_map(|(score, pv)| {
+                Some(MoveAnalysis {
+                    cmd: pv[0],
+                    score,
+                    breakdown: after(pv[0])?,
+                    pv,
+                })
+            })
+            .collect()
+    } else {
+        get_valid_commands(&root, player)
+            .into_iter()
+            .filter_map(|cmd| {
+                let breakdown = after(cmd)?;
+                Some(MoveAnalysis {
+                    cmd,
+                    score: breakdown.total,
+                    pv: vec![cmd],
+                    breakdown,
+                })
+            })
+            .collect()
+    };
+    moves.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    Analysis {
+        player,
+        position: heuristic_breakdown(&root, player, weights),
+        moves,
+        depth,
+        nodes: search.nodes(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AI, Difficulty};
+    use underflow_core::server::FlowServerConfig;
+
+    #[test]
+    fn test_analysis() {
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        let filling = analyze(
+            &server,
+            0,
+            &SearchBudget::depth(2),
+            Default::default(),
+            &Default::default(),
+            0,
+        );
+        assert_eq!(filling.moves.len(), 16);
+        assert_eq!(filling.depth, 0);
+
+        while server.phase == GamePhase::Filling {
+            let player = server.current_player;
+            let cmd = AI::with_seed(player, Difficulty::Easy, 0)
+                .make_move(&mut server)
+                .unwrap();
+            server.handle(cmd).unwrap();
+        }
+        let player = server.current_player;
+        let ai = AI::with_seed(player, Difficulty::Hard, 0).with_budget(SearchBudget::depth(2));
+        let analysis = ai.analyze(&server);
+        assert_eq!(analysis.depth, 2);
+        assert_eq!(
+            analysis.moves.len(),
+            get_valid_commands(&server, player).len()
+        );
+        assert!(analysis.moves.windows(2).all(|w| w[0].score >= w[1].score));
+        let best = analysis.best().unwrap();
+        assert_eq!(best.pv[0], best.cmd);
+        assert_eq!(Some(best.cmd), ai.make_move(&mut server.clone()).ok());
+    }
+}
@@ -0,0 +1,141 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use underflow_core::{
+    protocol::{FlowCommand, GamePhase},
+    server::FlowServer,
+};
+
+use crate::search::SearchContext;
+use crate::{OperationError, evaluate_filling_position, get_valid_commands};
+
+/// Completions sampled for every candidate cell
+const FILLING_SAMPLES: usize = 12;
+
+/// Fill the rest of the board with random cells. The server picks who fills next, so the reverse
+/// turn order of [underflow_core::rules::FillingOrder] is followed as it will be played.
+fn complete(server: &mut FlowServer, rng: &mut StdRng) {
+    while server.phase == GamePhase::Filling {
+        let player = server.current_player;
+        let Some(cmd) = get_valid_commands(server, player).into_iter().choose(rng) else {
+            break;
+        };
+        if server.handle(cmd).is_err() {
+            break;
+        }
+    }
+}
+
+pub(crate) struct FillingStrategy;
+
+impl FillingStrategy {
+    /// Mean heuristic for the root player of the flowing positions the cell leads to, over
+    /// sampled completions. Every sample counts as a node, once the budget is spent the mean is
+    /// taken over the samples so far, at least one.
+    pub fn evaluate(
+        search: &SearchContext,
+        server: &FlowServer,
+        cmd: FlowCommand,
+        seed: u64,
+    ) -> f64 {
+        let rng = &mut StdRng::seed_from_u64(seed);
+        let mut total = 0.0;
+        let mut samples = 0;
+        while samples < FILLING_SAMPLES && (search.visit() || samples == 0) {
+            let mut completion = server.fork();
+            if completion.handle(cmd).is_err() {
+                return f64::NEG_INFINITY;
+            }
+            complete(&mut completion, rng);
+            total += search.evaluate(&completion, search.root_player);
+            samples += 1;
+        }
+        total / samples as f64
+    }
+
+    /// The cell of the root player with the best sampled future, the distance to the edge breaks
+    /// ties
+    pub fn make_move(
+        search: &SearchContext,
+        server: &FlowServer,
+        rng: &mut StdRng,
+    ) -> Result<FlowCommand, OperationError> {
+        let player_id = search.root_player;
+        let (width, height) = (server.board.width() as i32, server.board.height() as i32);
+        let commands: Vec<_> = get_valid_commands(server, player_id)
+            .into_iter()
+            .map(|cmd| (cmd, rng.random::<u64>()))
+            .collect();
+        let scored: Vec<_> = commands
+            .into_par_iter()
+            .map(|(cmd, seed)| {
+                let FlowCommand::SetOccupied { x, y, .. } = cmd else {
+                    return (f64::NEG_INFINITY, i32::MIN, cmd);
+                };
+                let edge = evaluate_filling_position(width, height, x as i32, y as i32);
+                let score = FillingStrategy::evaluate(search, server, cmd, seed);
+                (score, edge, cmd)
+            })
+            .collect();
+
+        let best = scored
+            .iter()
+            .map(|&(score, edge, _)| (score, edge))
+            .reduce(|a, b| if b > a { b } else { a })
+            .ok_or(OperationError::NoValidMove)?;
+        scored
+            .into_iter()
+            .filter(|&(score, edge, _)| (score, edge) == best)
+            .map(|(_, _, cmd)| cmd)
+            .choose(rng)
+            .ok_or(OperationError::NoValidMove)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::SearchBudget;
+    use underflow_core::{CellState, server::FlowServerConfig};
+
+    #[test]
+    fn test_filling() {
+        let mut server = FlowServer::new(FlowServerConfig::new(3, 5)).unwrap();
+        while server.phase == GamePhase::Filling {
+            let player = server.current_player;
+            let pick = |seed| {
+                let rng = &mut StdRng::seed_from_u64(seed);
+                let search =
+                    SearchContext::new(player, &SearchBudget::default(), Default::default());
+                FillingStrategy::make_move(&search, &server, rng)
+            };
+            let cmd = pick(3).unwrap();
+            assert_eq!(pick(3).unwrap(), cmd);
+            let FlowCommand::SetOccupied { x, y, .. } = cmd else {
+                panic!("{:?} while filling", cmd);
+            };
+            assert_eq!(server.board.get(x, y), CellState::Empty);
+            server.handle(cmd).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_filling_budget() {
+        let server = FlowServer::new(FlowServerConfig::new(3, 6)).unwrap();
+        let player = server.current_player;
+        let candidates = get_valid_commands(&server, player).len() as u64;
+        let nodes = |budget| {
+            let search = SearchContext::new(player, &budget, Default::default());
+            let rng = &mut StdRng::seed_from_u64(0);
+            let cmd = FillingStrategy::make_move(&search, &server, rng).unwrap();
+            assert!(server.clone().handle(cmd).is_ok());
+            search.nodes()
+        };
+        assert_eq!(
+            nodes(SearchBudget::default()),
+            candidates * FILLING_SAMPLES as u64
+        );
+        // Past the budget every cell keeps its first sample only
+        assert!(nodes(SearchBudget::nodes(1)) <= 2 * candidates);
+    }
+}
@@ -0,0 +1,431 @@
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use underflow_core::{
+    CellState,
+    protocol::{FlowCommand, FlowError, GamePhase},
+    server::FlowServer,
+};
+
+use crate::filling::FillingStrategy;
+use crate::search::{
+    RootTables, SearchAlgorithm, SearchBudget, SearchContext, SearchStats, TranspositionTable,
+};
+use crate::{GameOverCheck, HeuristicWeights, MediumStrategy, OperationError, is_redundant_flow};
+
+/// Score and the expected line of play
+pub(crate) type Line<V> = (V, Vec<FlowCommand>);
+
+pub(crate) struct HardStrategy;
+
+/// Positions after every legal move of the player to move, the moves of [crate::get_valid_commands]
+fn children(server: &FlowServer) -> Vec<(FlowCommand, FlowServer)> {
+    server
+        .successors(server.current_player)
+        .into_iter()
+        .filter(|(cmd, _)| !is_redundant_flow(server, cmd))
+        .collect()
+}
+
+fn alive(server: &FlowServer, player: u8) -> bool {
+    server
+        .board
+        .cells()
+        .any(|cell| cell == CellState::Occupied(player))
+}
+
+/// The best line, ties go to the first move
+pub(crate) fn best_line(lines: Vec<Line<f64>>) -> Option<Line<f64>> {
+    lines
+        .into_iter()
+        .reduce(|best, line| if line.0 > best.0 { line } else { best })
+}
+
+fn scores(search: &SearchContext, server: &FlowServer) -> Vec<f64> {
+    (0..server.player_count())
+        .map(|player| search.evaluate(server, player))
+        .collect()
+}
+
+impl HardStrategy {
+    /// Iterative deepening, the move of the last completed depth is played
+    pub fn make_move(
+        player_id: u8,
+        server: &mut FlowServer,
+        rng: &mut StdRng,
+        budget: &SearchBudget,
+        algorithm: SearchAlgorithm,
+        weights: &HeuristicWeights,
+    ) -> Result<(FlowCommand, SearchStats), OperationError> {
+        // The search expands the player to move, which has to be the one it plays for
+        if server.current_player != player_id {
+            return Err(OperationError::ServerError(FlowError::NotYourTurn));
+        }
+        let search = SearchContext::new(player_id, budget, *weights);
+        if server.phase == GamePhase::Filling {
+            let cmd = FillingStrategy::make_move(&search, server, rng)?;
+            return Ok((cmd, search.stats(0, vec![cmd])));
+        }
+
+        let best = HardStrategy::deepen(&search, server, budget.max_depth, algorithm)
+            .and_then(|(depth, lines)| best_line(lines).map(|(_, pv)| (depth, pv)));
+        match best {
+            Some((depth, pv)) => Ok((pv[0], search.stats(depth, pv))),
+            // Fallback to medium strategy
+            None => MediumStrategy::make_move(player_id, server, rng, weights)
+                .map(|cmd| (cmd, search.stats(0, vec![cmd]))),
+        }
+    }
+
+    /// Iterative deepening, the lines of every root move from the last completed depth
+    pub(crate) fn deepen(
+        search: &SearchContext,
+        server: &FlowServer,
+        max_depth: u32,
+        algorithm: SearchAlgorithm,
+    ) -> Option<(u32, Vec<Line<f64>>)> {
+        let mut best = None;
+        let mut tables = Vec::new();
+        for depth in 1..=max_depth {
+            let lines = HardStrategy::root_lines(search, server, depth, algorithm, &mut tables);
+            if search.aborted() {
+                break;
+            }
+            if !lines.is_empty() {
+                best = Some((depth, lines));
+            }
+            // Every line already reaches the end of the game
+            if !search.take_cutoff() {
+                break;
+            }
+        }
+        best
+    }
+
+    /// The line of every root move, in move order. Root moves are searched in parallel, each with
+    /// tables of its own, so the result does not depend on thread timing. Pass the same `tables`
+    /// to every iteration to reuse what the shallower ones found.
+    pub(crate) fn root_lines(
+        search: &SearchContext,
+        server: &FlowServer,
+        depth: u32,
+        algorithm: SearchAlgorithm,
+        tables: &mut Vec<RootTables>,
+    ) -> Vec<Line<f64>> {
+        let root = search.root_player as usize;
+        let (inf, neg_inf) = (f64::INFINITY, f64::NEG_INFINITY);
+        let children = children(server);
+        tables.resize_with(children.len(), Default::default);
+        children
+            .into_par_iter()
+            .zip(tables.par_iter_mut())
+            .map(|((cmd, next), tables)| {
+                let (score, mut pv) = match algorithm {
+                    SearchAlgorithm::Paranoid => {
+                        let tt = &mut tables.paranoid;
+                        HardStrategy::paranoid(search, tt, &next, depth - 1, neg_inf, inf)
+                    }
+                    SearchAlgorithm::MaxN => {
+                        let tt = &mut tables.max_n;
+                        let (scores, pv) = HardStrategy::max_n(search, tt, &next, depth - 1);
+                        (scores[root], pv)
+                    }
+                    SearchAlgorithm::BestReply => {
+                        HardStrategy::best_reply(search, &next, depth - 1, neg_inf, inf, false)
+                    }
+                };
+                pv.insert(0, cmd);
+                (score, pv)
+            })
+            .collect()
+    }
+
+    /// Every other player minimizes the root's heuristic, fail-soft alpha-beta
+    fn paranoid(
+        search: &SearchContext,
+        tt: &mut TranspositionTable<f64>,
+        server: &FlowServer,
+        depth: u32,
+        mut alpha: f64,
+        mut beta: f64,
+    ) -> Line<f64> {
+        let root = search.root_player;
+        if !search.visit() {
+            return (0.0, Vec::new());
+        }
+        if server.game_over() {
+            return (search.evaluate(server, root), Vec::new());
+        }
+        if depth == 0 {
+            search.mark_cutoff();
+            return (search.evaluate(server, root), Vec::new());
+        }
+        let key = server.state_hash();
+        if let Some(hit) = tt.probe(key, depth) {
+            search.mark_cutoff();
+            return hit;
+        }
+
+        let maximizing = server.current_player == root;
+        let window = (alpha, beta);
+        let mut best: Option<Line<f64>> = None;
+        for (cmd, next) in children(server) {
+            let (score, mut pv) = HardStrategy::paranoid(search, tt, &next, depth - 1, alpha, beta);
+            let better = best
+                .as_ref()
+                .is_none_or(|(b, _)| if maximizing { score > *b } else { score < *b });
+            if better {
+                pv.insert(0, cmd);
+                best = Some((score, pv));
+            }
+            if maximizing {
+                alpha = alpha.max(score);
+            } else {
+                beta = beta.min(score);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        // A player without a legal move leaves the position as it is
+        let line = best.unwrap_or_else(|| (search.evaluate(server, root), Vec::new()));
+        // Only scores inside the window are exact
+        if !search.aborted() && window.0 < line.0 && line.0 < window.1 {
+            tt.store(key, depth, line.0, &line.1);
+        }
+        line
+    }
+
+    /// Every player maximizes their own entry of the score vector
+    fn max_n(
+        search: &SearchContext,
+        tt: &mut TranspositionTable<Vec<f64>>,
+        server: &FlowServer,
+        depth: u32,
+    ) -> Line<Vec<f64>> {
+        if !search.visit() {
+            return (vec![0.0; server.player_count() as usize], Vec::new());
+        }
+        if server.game_over() {
+            return (scores(search, server), Vec::new());
+        }
+        if depth == 0 {
+            search.mark_cutoff();
+            return (scores(search, server), Vec::new());
+        }
+        let key = server.state_hash();
+        if let Some(hit) = tt.probe(key, depth) {
+            search.mark_cutoff();
+            return hit;
+        }
+
+        let player = server.current_player as usize;
+        let mut best: Option<Line<Vec<f64>>> = None;
+        for (cmd, next) in children(server) {
+            let (scores, mut pv) = HardStrategy::max_n(search, tt, &next, depth - 1);
+            if best
+                .as_ref()
+                .is_none_or(|(b, _)| scores[player] > b[player])
+            {
+                pv.insert(0, cmd);
+                best = Some((scores, pv));
+            }
+        }
+
+        let line = best.unwrap_or_else(|| (scores(search, server), Vec::new()));
+        if !search.aborted() {
+            tt.store(key, depth, line.0.clone(), &line.1);
+        }
+        line
+    }
+
+    /// Max layers are the root's moves, min layers every move of every opponent, played out of
+    /// turn. Fail-soft alpha-beta.
+    fn best_reply(
+        search: &SearchContext,
+        server: &FlowServer,
+        depth: u32,
+        mut alpha: f64,
+        mut beta: f64,
+        maximizing: bool,
+    ) -> Line<f64> {
+        let root = search.root_player;
+        if !search.visit() {
+            return (0.0, Vec::new());
+        }
+        if server.game_over() || !alive(server, root) {
+            return (search.evaluate(server, root), Vec::new());
+        }
+        if depth == 0 {
+            search.mark_cutoff();
+            return (search.evaluate(server, root), Vec::new());
+        }
+
+        let movers: Vec<u8> = if maximizing {
+            vec![root]
+        } else {
+            (0..server.player_count())
+                .filter(|&p| p != root && alive(server, p))
+                .collect()
+        };
+        let mut best: Option<Line<f64>> = None;
+        'movers: for mover in movers {
+            let mut turn = server.fork();
+            turn.current_player = mover;
+            for (cmd, mut next) in children(&turn) {
+                if !maximizing {
+                    next.current_player = root;
+                }
+                let (score, mut pv) =
+                    HardStrategy::best_reply(search, &next, depth - 1, alpha, beta, !maximizing);
+                let better = best
+                    .as_ref()
+                    .is_none_or(|(b, _)| if maximizing { score > *b } else { score < *b });
+                if better {
+                    pv.insert(0, cmd);
+                    best = Some((score, pv));
+                }
+                if maximizing {
+                    alpha = alpha.max(score);
+                } else {
+                    beta = beta.min(score);
+                }
+                if alpha >= beta {
+                    break 'movers;
+                }
+            }
+        }
+
+        best.unwrap_or_else(|| (search.evaluate(server, root), Vec::new()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AI, Difficulty, heuristic};
+    use underflow_core::server::FlowServerConfig;
+
+    /// A flowing position after a few seeded random moves
+    fn position(player_count: u8, size: u8, flows: u64) -> FlowServer {
+        let config = FlowServerConfig::new(player_count, size);
+        let mut server = FlowServer::new(config).unwrap();
+        let mut seed = 0;
+        while server.phase == GamePhase::Filling || seed < flows {
+            let player = server.current_player;
+            let cmd = AI::with_seed(player, Difficulty::Easy, seed)
+                .make_move(&mut server)
+                .unwrap();
+            server.handle(cmd).unwrap();
+            seed += 1;
+        }
+        assert!(!server.game_over());
+        server
+    }
+
+    fn brute_paranoid(server: &FlowServer, root: u8, depth: u32) -> f64 {
+        if depth == 0 || server.game_over() {
+            return heuristic(server, root);
+        }
+        let values = children(server)
+            .into_iter()
+            .map(|(_, next)| brute_paranoid(&next, root, depth - 1));
+        let value = if server.current_player == root {
+            values.fold(None, |best: Option<f64>, v| {
+                Some(best.map_or(v, |b| b.max(v)))
+            })
+        } else {
+            values.fold(None, |best: Option<f64>, v| {
+                Some(best.map_or(v, |b| b.min(v)))
+            })
+        };
+        value.unwrap_or_else(|| heuristic(server, root))
+    }
+
+    fn brute_scores(server: &FlowServer) -> Vec<f64> {
+        (0..server.player_count())
+            .map(|player| heuristic(server, player))
+            .collect()
+    }
+
+    fn brute_max_n(server: &FlowServer, depth: u32) -> Vec<f64> {
+        if depth == 0 || server.game_over() {
+            return brute_scores(server);
+        }
+        let player = server.current_player as usize;
+        children(server)
+            .into_iter()
+            .map(|(_, next)| brute_max_n(&next, depth - 1))
+            .reduce(|best, v| if v[player] > best[player] { v } else { best })
+            .unwrap_or_else(|| brute_scores(server))
+    }
+
+    fn brute_best_reply(server: &FlowServer, root: u8, depth: u32, maximizing: bool) -> f64 {
+        if depth == 0 || server.game_over() || !alive(server, root) {
+            return heuristic(server, root);
+        }
+        let mut values = Vec::new();
+        for mover in 0..server.player_count() {
+            if (mover == root) != maximizing || !alive(server, mover) {
+                continue;
+            }
+            let mut turn = server.fork();
+            turn.current_player = mover;
+            for (_, mut next) in children(&turn) {
+                next.current_player = root;
+                values.push(brute_best_reply(&next, root, depth - 1, !maximizing));
+            }
+        }
+        let value = if maximizing {
+            values.into_iter().reduce(f64::max)
+        } else {
+            values.into_iter().reduce(f64::min)
+        };
+        value.unwrap_or_else(|| heuristic(server, root))
+    }
+
+    /// Best first move and its value, ties go to the first move
+    fn brute_root(server: &FlowServer, value: impl Fn(&FlowServer) -> f64) -> (f64, FlowCommand) {
+        children(server)
+            .into_iter()
+            .map(|(cmd, next)| (value(&next), cmd))
+            .reduce(|best, line| if line.0 > best.0 { line } else { best })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        for (player_count, size, depth) in [(2, 4, 3), (3, 5, 2)] {
+            let server = position(player_count, size, 6);
+            let root = server.current_player;
+            let other = AI::new((root + 1) % player_count, Difficulty::Hard);
+            assert!(matches!(
+                other.make_move(&mut server.fork()),
+                Err(OperationError::ServerError(FlowError::NotYourTurn))
+            ));
+            let search =
+                || SearchContext::new(root, &SearchBudget::depth(depth), Default::default());
+            // Deepen like a real search, the tables carry over from one depth to the next
+            let found = |algorithm| {
+                let (search, tables) = (&search(), &mut Vec::new());
+                let mut lines = Vec::new();
+                for d in 1..=depth {
+                    lines = HardStrategy::root_lines(search, &server, d, algorithm, tables);
+                }
+                let (score, pv) = best_line(lines).unwrap();
+                (score, pv[0])
+            };
+
+            let expected = brute_root(&server, |next| brute_paranoid(next, root, depth - 1));
+            assert_eq!(found(SearchAlgorithm::Paranoid), expected);
+
+            let expected = brute_root(&server, |next| brute_max_n(next, depth - 1)[root as usize]);
+            assert_eq!(found(SearchAlgorithm::MaxN), expected);
+
+            let expected = brute_root(&server, |next| {
+                brute_best_reply(next, root, depth - 1, false)
+            });
+            assert_eq!(found(SearchAlgorithm::BestReply), expected);
+        }
+    }
+}
@@ -1,80 +1,232 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 use underflow_core::{Board, CellState, server::FlowServer};
 
+// ========================
+// HEURISTIC WEIGHTS
+// ========================
+
+/// Scales of the heuristic components. A profile is one `<key> <value>` line per weight, blank
+/// lines and lines starting with `#` are ignored and missing weights keep their defaults.
+///
+/// ```text
+/// safety 1
+/// balance 10
+/// diversity 1
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeuristicWeights {
+    /// Per move a cell of the player is away from being pushed off
+    pub safety: f64,
+    /// Bonus for opponents of equal strength, so they keep each other busy
+    pub balance: f64,
+    /// How much the balance bonus grows with every other player still on the board
+    pub diversity: f64,
+}
+
+impl Default for HeuristicWeights {
+    fn default() -> Self {
+        Self {
+            safety: 1.0,
+            balance: 10.0,
+            diversity: 1.0,
+        }
+    }
+}
+
+impl HeuristicWeights {
+    pub const KEYS: [&str; 3] = ["safety", "balance", "diversity"];
+
+    pub fn get(&self, key: &str) -> Option<f64> {
+        Some(match key {
+            "safety" => self.safety,
+            "balance" => self.balance,
+            "diversity" => self.diversity,
+            _ => return None,
+        })
+    }
+
+    /// `false` for an unknown key
+    pub fn set(&mut self, key: &str, value: f64) -> bool {
+        match key {
+            "safety" => self.safety = value,
+            "balance" => self.balance = value,
+            "diversity" => self.diversity = value,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ProfileError> {
+        std::fs::read_to_string(path)
+            .map_err(|e| ProfileError::Io(e.to_string()))?
+            .parse()
+    }
+}
+
+impl Display for HeuristicWeights {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for key in Self::KEYS {
+            writeln!(f, "{} {}", key, self.get(key).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileError {
+    Io(String),
+
+    /// Line number (1-based) and its content
+    Syntax(usize, String),
+
+    UnknownWeight(String),
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Io(e) => write!(f, "Cannot read profile: {}", e),
+            ProfileError::Syntax(line, s) => write!(f, "Syntax error at line {}: {:?}", line, s),
+            ProfileError::UnknownWeight(key) => write!(f, "Unknown weight `{}`", key),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl FromStr for HeuristicWeights {
+    type Err = ProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = HeuristicWeights::default();
+        for (idx, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let syntax = || ProfileError::Syntax(idx + 1, line.to_string());
+            let (key, value) = line.split_once(char::is_whitespace).ok_or_else(syntax)?;
+            let value = value.trim().parse().map_err(|_| syntax())?;
+            if !weights.set(key, value) {
+                return Err(ProfileError::UnknownWeight(key.to_string()));
+            }
+        }
+        Ok(weights)
+    }
+}
+
 // ========================
 // HEURISTIC FUNCTION
 // ========================
 
+/// [heuristic_with] the default weights
 pub fn heuristic(server: &FlowServer, player_id: u8) -> f64 {
+    heuristic_with(server, player_id, &HeuristicWeights::default())
+}
+
+pub fn heuristic_with(server: &FlowServer, player_id: u8, weights: &HeuristicWeights) -> f64 {
+    heuristic_breakdown(server, player_id, weights).total
+}
+
+/// The weighted components of [heuristic_with]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeuristicBreakdown {
+    /// Weighted sum of how far the player's cells are from being pushed off
+    pub safety: f64,
+    /// Weighted bonus for evenly matched opponents
+    pub balance: f64,
+    /// Factor of the balance bonus from the number of players on the board
+    pub diversity: f64,
+    /// `safety + balance * diversity`, negative infinity once the player has no cell left
+    pub total: f64,
+}
+
+pub fn heuristic_breakdown(
+    server: &FlowServer,
+    player_id: u8,
+    weights: &HeuristicWeights,
+) -> HeuristicBreakdown {
     let board = &server.board;
-    let size = board.size();
+    let wrap = server.rules().flow.is_wrap();
     let mut player_strength = HashMap::new();
     let mut my_strength = 0.0;
 
     // First stage: Calculate player strength
     let anchor_lock_map = get_anchor_lock_state(board);
 
-    for x in 0..size {
-        for y in 0..size {
-            let cell_state = board.get(x, y);
-
-            let min_moves_to_boundary =
-                calculate_min_moves_to_boundary(x, y, size, &anchor_lock_map);
+    for (x, y) in board.positions() {
+        let cell_state = board.get(x, y);
 
-            if let CellState::Occupied(id) | CellState::Anchored(id) = cell_state {
-                let safety = if matches!(cell_state, CellState::Anchored(_)) {
-                    0.0 // Anchor is not counted in safety
-                } else {
-                    min_moves_to_boundary
-                };
+        if let CellState::Occupied(id) | CellState::Anchored(id) = cell_state {
+            let safety = if matches!(cell_state, CellState::Anchored(_)) {
+                0.0 // Anchor is not counted in safety
+            } else if wrap {
+                // Nothing is pushed off in wrap mode, every cell is as safe as a locked one
+                board.row_lane(y).len().max(board.col_lane(x).len()) as f64 / 2.0 + 1.0
+            } else {
+                calculate_min_moves_to_boundary(board, x, y, &anchor_lock_map)
+            };
 
-                *player_strength.entry(id).or_insert(0.0) += safety;
+            *player_strength.entry(id).or_insert(0.0) += safety;
 
-                if id == player_id {
-                    my_strength += safety;
-                }
+            if id == player_id {
+                my_strength += safety;
             }
         }
     }
 
-    let balance_score = calculate_balance_score(&player_strength, player_id);
+    let balance_score = weights.balance * calculate_balance_score(&player_strength, player_id);
 
-    let diversity_bonus = player_strength.len().max(1) as f64;
+    let diversity_bonus = 1.0 + weights.diversity * (player_strength.len().max(1) - 1) as f64;
 
-    let score = my_strength + balance_score * diversity_bonus;
+    let safety = weights.safety * my_strength;
+    let score = safety + balance_score * diversity_bonus;
 
-    if my_strength == 0.0 {
-        return f64::NEG_INFINITY; // If the player has no strength, return negative infinity
-    } else {
-        score
+    HeuristicBreakdown {
+        safety,
+        balance: balance_score,
+        diversity: diversity_bonus,
+        total: if my_strength == 0.0 {
+            f64::NEG_INFINITY // If the player has no strength, return negative infinity
+        } else {
+            score
+        },
     }
 }
 
+/// Blocked cells are skipped by flows, so distances are counted along the row / column lanes
 pub fn calculate_min_moves_to_boundary(
+    board: &Board,
     x: u8,
     y: u8,
-    size: u8,
     lock_map: &(HashSet<u8>, HashSet<u8>, HashSet<(u8, u8)>),
 ) -> f64 {
+    let row = board.row_lane(y);
+    let col = board.col_lane(x);
+
     if lock_map.2.contains(&(x, y)) {
         // If the cell is locked, return the moves of a center cell
-        return size as f64 / 2.0 + 1.0;
+        return row.len().max(col.len()) as f64 / 2.0 + 1.0;
     }
 
-    let left_distance = (x + 1) as f64;
-    let right_distance = (size - x) as f64;
-    let top_distance = (y + 1) as f64;
-    let bottom_distance = (size - y) as f64;
+    let row_idx = row.iter().position(|&(cx, _)| cx == x).unwrap_or(0);
+    let col_idx = col.iter().position(|&(_, cy)| cy == y).unwrap_or(0);
+    let left_distance = (row_idx + 1) as f64;
+    let right_distance = (row.len() - row_idx) as f64;
+    let top_distance = (col_idx + 1) as f64;
+    let bottom_distance = (col.len() - col_idx) as f64;
 
     // Calculate the minimum distance to the boundary
     let row_moves = if lock_map.0.contains(&y) {
-        size as f64 / 2.0 + 1.0
+        row.len() as f64 / 2.0 + 1.0
     } else {
         left_distance.min(right_distance)
     };
 
     let col_moves = if lock_map.1.contains(&x) {
-        size as f64 / 2.0 + 1.0
+        col.len() as f64 / 2.0 + 1.0
     } else {
         top_distance.min(bottom_distance)
     };
@@ -91,7 +243,7 @@ fn calculate_balance_score(player_strength: &HashMap<u8, f64>, player_id: u8) ->
         .map(|(_, &strength)| strength)
         .collect();
 
-    if other_strength.len() == 0 || other_strength.len() == 1 {
+    if other_strength.len() <= 1 {
         return 0.0; // No other players, balance score is zero
     }
 
@@ -102,7 +254,7 @@ fn calculate_balance_score(player_strength: &HashMap<u8, f64>, player_id: u8) ->
         .sum::<f64>()
         / other_strength.len() as f64;
 
-    10.0 / (1.0 + var.sqrt())
+    1.0 / (1.0 + var.sqrt())
 }
 
 fn get_anchor_lock_state(board: &Board) -> (HashSet<u8>, HashSet<u8>, HashSet<(u8, u8)>) {
@@ -110,14 +262,11 @@ fn get_anchor_lock_state(board: &Board) -> (HashSet<u8>, HashSet<u8>, HashSet<(u
     let mut locked_cols = HashSet::new();
     let mut locked_cells = HashSet::new();
     let mut anchor_positions = HashSet::new();
-    let size = board.size();
 
     // Get all existed anchors positions
-    for x in 0..size {
-        for y in 0..size {
-            if let CellState::Anchored(_) = board.get(x, y) {
-                anchor_positions.insert((x, y));
-            }
+    for (x, y) in board.positions() {
+        if let CellState::Anchored(_) = board.get(x, y) {
+            anchor_positions.insert((x, y));
         }
     }
 
@@ -1,14 +1,26 @@
 use rand::prelude::*;
-use rayon::prelude::*;
+use rand::rngs::StdRng;
 use underflow_core::protocol::FlowCommand;
 use underflow_core::protocol::GamePhase;
 use underflow_core::server::*;
 
+pub mod analysis;
+mod filling;
+mod hard;
 mod heuristic;
+mod mcts;
+mod search;
+pub mod tournament;
+pub mod tuner;
 mod util;
 pub use heuristic::*;
+pub use mcts::Playout;
+pub use search::{SearchAlgorithm, SearchBudget, SearchStats};
 pub use util::*;
 
+use hard::HardStrategy;
+use mcts::MctsStrategy;
+
 /// AI difficulties
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,27 +28,117 @@ pub enum Difficulty {
     Easy,
     Medium,
     Hard,
+    /// Monte Carlo tree search
+    Mcts,
 }
 
 pub struct AI {
     player_id: u8,
     difficulty: Difficulty,
+    /// Ties are broken with an RNG seeded from this, so the same seed and position always give
+    /// the same move
+    seed: u64,
+    budget: SearchBudget,
+    algorithm: SearchAlgorithm,
+    playout: Playout,
+    weights: HeuristicWeights,
 }
 
 impl AI {
+    /// AI with a random seed
     pub fn new(player_id: u8, difficulty: Difficulty) -> Self {
+        Self::with_seed(player_id, difficulty, rand::random())
+    }
+
+    pub fn with_seed(player_id: u8, difficulty: Difficulty, seed: u64) -> Self {
         AI {
             player_id,
             difficulty,
+            seed,
+            budget: SearchBudget::default(),
+            algorithm: SearchAlgorithm::default(),
+            playout: Playout::default(),
+            weights: HeuristicWeights::default(),
         }
     }
 
+    /// Limit the search of [Difficulty::Hard] and [Difficulty::Mcts]
+    pub fn with_budget(mut self, budget: SearchBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Choose how [Difficulty::Hard] models the opponents
+    pub fn with_algorithm(mut self, algorithm: SearchAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Choose how [Difficulty::Mcts] plays positions out
+    pub fn with_playout(mut self, playout: Playout) -> Self {
+        self.playout = playout;
+        self
+    }
+
+    /// Evaluate positions with these weights instead of the defaults, e.g. a tuned profile
+    pub fn with_weights(mut self, weights: HeuristicWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     /// Make a move based on the AI's difficulty level
     pub fn make_move(&self, server: &mut FlowServer) -> Result<FlowCommand, OperationError> {
+        self.search(server).map(|(cmd, _)| cmd)
+    }
+
+    /// Every legal move of the AI's player with its score and the reasons for it, searched like
+    /// [Difficulty::Hard] whatever the difficulty
+    pub fn analyze(&self, server: &FlowServer) -> analysis::Analysis {
+        analysis::analyze(
+            server,
+            self.player_id,
+            &self.budget,
+            self.algorithm,
+            &self.weights,
+            self.seed,
+        )
+    }
+
+    /// Like [AI::make_move], also report how the search went
+    pub fn search(
+        &self,
+        server: &mut FlowServer,
+    ) -> Result<(FlowCommand, SearchStats), OperationError> {
+        let rng = &mut StdRng::seed_from_u64(self.seed);
+        let unsearched = |cmd| (cmd, SearchStats::from_move(cmd));
         match self.difficulty {
-            Difficulty::Easy => SimpleStrategy::make_move(self.player_id, server),
-            Difficulty::Medium => MediumStrategy::make_move(self.player_id, server),
-            Difficulty::Hard => HardStrategy::make_move(self.player_id, server),
+            Difficulty::Easy => {
+                SimpleStrategy::make_move(self.player_id, server, rng).map(unsearched)
+            }
+            Difficulty::Medium => {
+                MediumStrategy::make_move(self.player_id, server, rng, &self.weights)
+                    .map(unsearched)
+            }
+            Difficulty::Hard => HardStrategy::make_move(
+                self.player_id,
+                server,
+                rng,
+                &self.budget,
+                self.algorithm,
+                &self.weights,
+            ),
+            Difficulty::Mcts => MctsStrategy::make_move(
+                self.player_id,
+                server,
+                rng,
+                &self.budget,
+                self.playout,
+                &self.weights,
+            ),
         }
     }
 }
@@ -49,19 +151,18 @@ struct SimpleStrategy;
 
 struct MediumStrategy;
 
-struct HardStrategy;
-
 impl SimpleStrategy {
     /// Randomly select and execute a valid command
     pub fn make_move(
         player_id: u8,
         server: &mut FlowServer,
+        rng: &mut StdRng,
     ) -> Result<FlowCommand, OperationError> {
         // Get all valid commands for the player
         let commands = get_valid_commands(server, player_id);
 
         // Randomly select one command
-        if let Some(cmd) = commands.into_iter().choose(&mut rand::rng()) {
+        if let Some(cmd) = commands.into_iter().choose(rng) {
             return Ok(cmd);
         }
 
@@ -74,6 +175,8 @@ impl MediumStrategy {
     pub fn make_move(
         player_id: u8,
         server: &mut FlowServer,
+        rng: &mut StdRng,
+        weights: &HeuristicWeights,
     ) -> Result<FlowCommand, OperationError> {
         let mut best_score = f64::NEG_INFINITY;
         let mut best_commands = Vec::new();
@@ -83,14 +186,14 @@ impl MediumStrategy {
 
         if server.phase == GamePhase::Filling {
             // If in filling phase, use simple strategy
-            return MediumStrategy::filling_move(server, commands);
+            return MediumStrategy::filling_move(server, commands, rng);
         }
 
         // evaluate each command using the heuristic function
         for cmd in commands {
-            match try_handle_command(server, player_id, cmd.clone()) {
+            match try_handle_command(server, player_id, cmd) {
                 Ok(new_server) => {
-                    let score = heuristic(&new_server, player_id);
+                    let score = heuristic_with(&new_server, player_id, weights);
                     if score > best_score {
                         best_score = score;
                         best_commands.clear();
@@ -104,28 +207,29 @@ impl MediumStrategy {
         }
 
         // choose a command from the best commands found
-        if let Some(cmd) = best_commands.into_iter().choose(&mut rand::rng()) {
+        if let Some(cmd) = best_commands.into_iter().choose(rng) {
             return Ok(cmd);
         }
 
         // if no valid commands found, fallback to simple strategy
-        SimpleStrategy::make_move(player_id, server)
+        SimpleStrategy::make_move(player_id, server, rng)
     }
 
-    fn filling_move(
+    pub(crate) fn filling_move(
         server: &mut FlowServer,
         commands: Vec<FlowCommand>,
+        rng: &mut StdRng,
     ) -> Result<FlowCommand, OperationError> {
         let mut best_score = i32::MIN;
         let mut best_commands = Vec::new();
         let borad = &server.board;
-        let size = borad.size();
+        let (width, height) = (borad.width() as i32, borad.height() as i32);
 
         // Choose the best command
         for cmd in commands {
             if let FlowCommand::SetOccupied { x, y, .. } = cmd {
                 // Check if the cell is empty
-                let score = evaluate_filling_position(size as i32, x as i32, y as i32);
+                let score = evaluate_filling_position(width, height, x as i32, y as i32);
                 if score > best_score {
                     best_score = score;
                     best_commands.clear();
@@ -136,115 +240,62 @@ impl MediumStrategy {
             }
         }
         // choose a command from the best commands found
-        if let Some(cmd) = best_commands.into_iter().choose(&mut rand::rng()) {
+        if let Some(cmd) = best_commands.into_iter().choose(rng) {
             return Ok(cmd);
         }
 
-        return Err(OperationError::NoValidMove);
+        Err(OperationError::NoValidMove)
     }
 }
 
-impl HardStrategy {
-    pub fn make_move(
-        player_id: u8,
-        server: &mut FlowServer,
-    ) -> Result<FlowCommand, OperationError> {
-        if server.phase == GamePhase::Filling {
-            return MediumStrategy::filling_move(server, get_valid_commands(server, player_id));
-        }
-
-        let depth: i32 = match server.player_count() {
-            2 => 3,
-            3 => 3,
-            4 => 3,
-            _ => 3,
-        };
-
-        let (_, command) = HardStrategy::maxn_search(
-            server,
-            player_id,
-            player_id,
-            depth,
-            f64::NEG_INFINITY,
-            f64::INFINITY,
-        );
-
-        command.map(Ok).unwrap_or_else(|| {
-            // Fallback to medium strategy
-            MediumStrategy::make_move(player_id, server)
-        })
-    }
-
-    fn maxn_search(
-        server: &FlowServer,
-        current_player: u8,
-        root_player: u8,
-        depth: i32,
-        alpha: f64,
-        beta: f64,
-    ) -> (f64, Option<FlowCommand>) {
-        if depth <= 0 || server.game_over() {
-            return (heuristic(server, root_player), None);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A flowing position on a small board
+    fn flowing_server() -> FlowServer {
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        let mut seed = 0;
+        while server.phase == GamePhase::Filling || seed < 24 {
+            let player = server.current_player;
+            let cmd = AI::with_seed(player, Difficulty::Easy, seed)
+                .make_move(&mut server)
+                .unwrap();
+            server.handle(cmd).unwrap();
+            seed += 1;
+            if server.winning().is_some() {
+                break;
+            }
         }
+        server
+    }
 
-        // Get all valid commands for the current player
-        let commands = get_valid_commands(server, current_player);
-
-        let is_root = current_player == root_player;
-        let mut best_score = if is_root {
-            f64::NEG_INFINITY
-        } else {
-            f64::INFINITY
-        };
-        let mut best_cmd = None;
-
-        let results: Vec<_> = commands
-            .par_iter()
-            .filter_map(|cmd| {
-                let mut new_server = server.clone();
-
-                if new_server.handle(cmd.clone()).is_err() {
-                    return None;
-                }
-
-                let next_player = new_server.current_player;
-                let (score, _) = HardStrategy::maxn_search(
-                    &new_server,
-                    next_player,
-                    root_player,
-                    depth - 1,
-                    alpha,
-                    beta,
-                );
-
-                Some((score, cmd.clone()))
-            })
-            .collect();
-
-        let mut current_alpha = alpha;
-        let mut current_beta = beta;
-
-        for (score, cmd) in results {
-            if is_root {
-                if score > best_score {
-                    best_score = score;
-                    best_cmd = Some(cmd.clone());
-                    current_alpha = current_alpha.max(score);
-                }
-
-                // Alpha-Beta pruning
-                if best_score >= current_beta {
-                    break;
-                }
-            } else {
-                if score < best_score {
-                    best_score = score;
-                    best_cmd = Some(cmd.clone());
-                    current_beta = current_beta.min(score);
-                }
+    #[test]
+    fn test_seeded_ai() {
+        let server = flowing_server();
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let ai = AI::with_seed(server.current_player, difficulty, 42);
+            let first = ai.make_move(&mut server.clone()).ok();
+            for _ in 0..3 {
+                assert_eq!(ai.make_move(&mut server.clone()).ok(), first);
             }
         }
+    }
 
-        (best_score, best_cmd)
+    #[test]
+    fn test_search_budget() {
+        let server = flowing_server();
+        let player = server.current_player;
+        let ai = AI::with_seed(player, Difficulty::Hard, 0).with_budget(SearchBudget::depth(2));
+        let (cmd, stats) = ai.search(&mut server.clone()).unwrap();
+        assert!(stats.depth >= 1 && stats.depth <= 2);
+        assert_eq!(stats.pv[0], cmd);
+        assert!(stats.nodes > 0);
+
+        // Not even the first depth fits, the fallback still moves
+        let ai = AI::with_seed(player, Difficulty::Hard, 0).with_budget(SearchBudget::nodes(1));
+        let (cmd, stats) = ai.search(&mut server.clone()).unwrap();
+        assert_eq!(stats.depth, 0);
+        assert!(server.clone().handle(cmd).is_ok());
     }
 }
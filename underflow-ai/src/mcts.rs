@@ -0,0 +1,370 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use underflow_core::{
+    CellState,
+    protocol::{FlowCommand, GamePhase},
+    server::FlowServer,
+};
+
+use crate::filling::FillingStrategy;
+use crate::search::{SearchBudget, SearchContext, SearchStats};
+use crate::{HeuristicWeights, MediumStrategy, OperationError, get_valid_commands, heuristic_with};
+
+/// Independent trees searched in parallel, a fixed count so the result does not depend on the
+/// number of threads
+const ROOT_TREES: usize = 8;
+/// Iterations over all trees when the budget sets neither time nor nodes
+const DEFAULT_ITERATIONS: u64 = 2000;
+/// Moves played out before the position is scored
+const PLAYOUT_LENGTH: usize = 24;
+/// Chance of a random move in a heuristic playout
+const PLAYOUT_EPSILON: f64 = 0.25;
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+/// How the Monte Carlo AI plays a position out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Playout {
+    /// Uniformly random legal moves
+    #[default]
+    Random,
+    /// Mostly the move with the best heuristic for the mover, slower but more realistic
+    Heuristic,
+}
+
+struct Node {
+    /// Move leading here and the player who made it, `None` for the root
+    cmd: Option<(FlowCommand, u8)>,
+    children: Vec<usize>,
+    /// Candidates not expanded yet, the ones leading to a recurrence are dropped on expansion
+    untried: Vec<FlowCommand>,
+    visits: u32,
+    /// Summed reward of every player
+    rewards: Vec<f64>,
+}
+
+impl Node {
+    fn new(cmd: Option<(FlowCommand, u8)>, server: &FlowServer) -> Self {
+        Self {
+            cmd,
+            children: Vec::new(),
+            untried: candidates(server),
+            visits: 0,
+            rewards: vec![0.0; server.player_count() as usize],
+        }
+    }
+
+    /// Mean reward of the player who moved here
+    fn value(&self) -> f64 {
+        match self.cmd {
+            Some((_, mover)) => self.rewards[mover as usize] / self.visits as f64,
+            None => 0.0,
+        }
+    }
+}
+
+/// Candidate moves, none once the game is decided
+fn candidates(server: &FlowServer) -> Vec<FlowCommand> {
+    if server.winning().is_some() {
+        return Vec::new();
+    }
+    server.candidate_moves(server.current_player)
+}
+
+/// The winner takes everything, otherwise every player is scored by their share of the
+/// occupied cells
+fn rewards(server: &FlowServer) -> Vec<f64> {
+    let mut counts = vec![0.0; server.player_count() as usize];
+    for cell in server.board.cells() {
+        if let CellState::Occupied(player) = cell {
+            counts[player as usize] += 1.0;
+        }
+    }
+    let total: f64 = counts.iter().sum();
+    if total > 0.0 {
+        counts.iter_mut().for_each(|count| *count /= total);
+    }
+    counts
+}
+
+/// Play a random legal move, `false` if there is none
+fn random_move(server: &mut FlowServer, rng: &mut StdRng) -> bool {
+    let player = server.current_player;
+    let mut candidates = server.candidate_moves(player);
+    candidates.shuffle(rng);
+    candidates.into_iter().any(|cmd| server.handle(cmd).is_ok())
+}
+
+/// Play the move with the best heuristic for the mover, `false` if there is none
+fn greedy_move(server: &mut FlowServer, rng: &mut StdRng, weights: &HeuristicWeights) -> bool {
+    let player = server.current_player;
+    let mut best = None;
+    for cmd in server.candidate_moves(player) {
+        let mut next = server.fork();
+        if next.handle(cmd).is_err() {
+            continue;
+        }
+        // Random tie breaks
+        let score = (heuristic_with(&next, player, weights), rng.random::<u32>());
+        if best.as_ref().is_none_or(|(b, _)| score > *b) {
+            best = Some((score, next));
+        }
+    }
+    match best {
+        Some((_, next)) => {
+            *server = next;
+            true
+        }
+        None => false,
+    }
+}
+
+fn playout(
+    mut server: FlowServer,
+    rng: &mut StdRng,
+    policy: Playout,
+    weights: &HeuristicWeights,
+) -> Vec<f64> {
+    for _ in 0..PLAYOUT_LENGTH {
+        if server.winning().is_some() {
+            break;
+        }
+        let moved = match policy {
+            Playout::Heuristic if !rng.random_bool(PLAYOUT_EPSILON) => {
+                greedy_move(&mut server, rng, weights)
+            }
+            _ => random_move(&mut server, rng),
+        };
+        if !moved {
+            break;
+        }
+    }
+    rewards(&server)
+}
+
+struct Tree {
+    root: FlowServer,
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new(root: &FlowServer) -> Self {
+        Self {
+            root: root.fork(),
+            nodes: vec![Node::new(None, root)],
+        }
+    }
+
+    /// UCT, the child's value is seen from the player who chose it
+    fn select(&self, parent: usize) -> usize {
+        let log_visits = (self.nodes[parent].visits as f64).ln();
+        let uct = |&child: &usize| {
+            let node = &self.nodes[child];
+            node.value() + EXPLORATION * (log_visits / node.visits as f64).sqrt()
+        };
+        let children = &self.nodes[parent].children;
+        children
+            .iter()
+            .copied()
+            .reduce(|best, child| {
+                if uct(&child) > uct(&best) {
+                    child
+                } else {
+                    best
+                }
+            })
+            .unwrap()
+    }
+
+    fn iterate(&mut self, rng: &mut StdRng, policy: Playout, weights: &HeuristicWeights) {
+        let mut server = self.root.fork();
+        let mut path = vec![0];
+        let mut node = 0;
+        while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select(node);
+            let (cmd, _) = self.nodes[node].cmd.unwrap();
+            server.handle(cmd).expect("tree moves stay legal");
+            path.push(node);
+        }
+
+        let untried = &mut self.nodes[node].untried;
+        let mover = server.current_player;
+        while !untried.is_empty() {
+            let cmd = untried.swap_remove(rng.random_range(0..untried.len()));
+            if server.handle(cmd).is_ok() {
+                let child = self.nodes.len();
+                self.nodes.push(Node::new(Some((cmd, mover)), &server));
+                self.nodes[node].children.push(child);
+                path.push(child);
+                break;
+            }
+        }
+
+        let rewards = playout(server, rng, policy, weights);
+        for node in path {
+            let node = &mut self.nodes[node];
+            node.visits += 1;
+            node.rewards
+                .iter_mut()
+                .zip(&rewards)
+                .for_each(|(sum, reward)| *sum += reward);
+        }
+    }
+
+    fn most_visited(&self, node: usize) -> Option<usize> {
+        self.nodes[node]
+            .children
+            .iter()
+            .copied()
+            .reduce(|best, child| {
+                if self.nodes[child].visits > self.nodes[best].visits {
+                    child
+                } else {
+                    best
+                }
+            })
+    }
+
+    /// Most visited line below a root move
+    fn line(&self, cmd: FlowCommand) -> Vec<FlowCommand> {
+        let mut line = Vec::new();
+        let mut node = self.nodes[0]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].cmd.is_some_and(|(c, _)| c == cmd));
+        while let Some(current) = node {
+            line.push(self.nodes[current].cmd.unwrap().0);
+            node = self.most_visited(current);
+        }
+        line
+    }
+}
+
+pub(crate) struct MctsStrategy;
+
+impl MctsStrategy {
+    /// UCT with root parallelism, the root move visited most over all the trees is played.
+    /// A node budget counts iterations and is split evenly between the trees, `max_depth` is
+    /// ignored.
+    pub fn make_move(
+        player_id: u8,
+        server: &mut FlowServer,
+        rng: &mut StdRng,
+        budget: &SearchBudget,
+        policy: Playout,
+        weights: &HeuristicWeights,
+    ) -> Result<(FlowCommand, SearchStats), OperationError> {
+        if server.phase == GamePhase::Filling {
+            // Sampled completions count against the node budget like iterations
+            let search = SearchContext::new(player_id, budget, *weights);
+            let cmd = FillingStrategy::make_move(&search, server, rng)?;
+            return Ok((cmd, search.stats(0, vec![cmd])));
+        }
+        let root_moves = get_valid_commands(server, player_id);
+        match root_moves.as_slice() {
+            [] => return Err(OperationError::NoValidMove),
+            [cmd] => return Ok((*cmd, SearchStats::from_move(*cmd))),
+            _ => {}
+        }
+
+        let iterations = match (budget.nodes, budget.time) {
+            (None, None) => Some(DEFAULT_ITERATIONS),
+            (nodes, _) => nodes,
+        };
+        let per_tree = iterations.map(|n| n.div_ceil(ROOT_TREES as u64));
+        // Only the clock is shared, iterations are counted per tree
+        let search = SearchContext::new(
+            player_id,
+            &SearchBudget {
+                nodes: None,
+                ..*budget
+            },
+            *weights,
+        );
+        let seeds: Vec<u64> = (0..ROOT_TREES).map(|_| rng.random()).collect();
+        let trees: Vec<Tree> = seeds
+            .into_par_iter()
+            .map(|seed| {
+                let rng = &mut StdRng::seed_from_u64(seed);
+                let mut tree = Tree::new(server);
+                let mut done = 0;
+                while per_tree.is_none_or(|n| done < n) && search.visit() {
+                    tree.iterate(rng, policy, weights);
+                    done += 1;
+                }
+                tree
+            })
+            .collect();
+
+        // Sum the visits of every root move, ties go to the first move
+        let visits = |cmd: FlowCommand| -> u32 {
+            trees
+                .iter()
+                .flat_map(|tree| {
+                    tree.nodes[0]
+                        .children
+                        .iter()
+                        .map(|&child| &tree.nodes[child])
+                })
+                .filter(|node| node.cmd.is_some_and(|(c, _)| c == cmd))
+                .map(|node| node.visits)
+                .sum()
+        };
+        let scored: Vec<_> = root_moves.iter().map(|&cmd| (visits(cmd), cmd)).collect();
+        let (best_visits, best) = scored
+            .into_iter()
+            .reduce(|best, next| if next.0 > best.0 { next } else { best })
+            .unwrap();
+        if best_visits == 0 {
+            return MediumStrategy::make_move(player_id, server, rng, weights)
+                .map(|cmd| (cmd, search.stats(0, vec![cmd])));
+        }
+
+        // The tree that explored the chosen move the most gives the expected line
+        let pv = trees
+            .iter()
+            .map(|tree| tree.line(best))
+            .max_by_key(|line| line.len())
+            .unwrap_or_default();
+        let mut stats = search.stats(pv.len() as u32, pv);
+        stats.nodes = trees.iter().map(|tree| tree.nodes[0].visits as u64).sum();
+        Ok((best, stats))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AI, Difficulty};
+    use underflow_core::server::FlowServerConfig;
+
+    #[test]
+    fn test_mcts() {
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        while server.phase == GamePhase::Filling {
+            let player = server.current_player;
+            let cmd = AI::with_seed(player, Difficulty::Easy, 0)
+                .make_move(&mut server)
+                .unwrap();
+            server.handle(cmd).unwrap();
+        }
+
+        let budget = SearchBudget::nodes(200);
+        let player = server.current_player;
+        for policy in [Playout::Random, Playout::Heuristic] {
+            let search = |seed| {
+                let rng = &mut StdRng::seed_from_u64(seed);
+                let weights = &HeuristicWeights::default();
+                MctsStrategy::make_move(player, &mut server.clone(), rng, &budget, policy, weights)
+                    .unwrap()
+            };
+            let (cmd, stats) = search(7);
+            assert_eq!(stats.nodes, 200);
+            assert_eq!(stats.pv[0], cmd);
+            assert!(server.clone().handle(cmd).is_ok());
+            let (again, again_stats) = search(7);
+            assert_eq!((again, again_stats.pv), (cmd, stats.pv));
+        }
+    }
+}
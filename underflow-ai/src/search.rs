@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use underflow_core::{protocol::FlowCommand, server::FlowServer};
+
+use crate::{HeuristicWeights, heuristic_with};
+
+/// Limits of one search, deepening stops at whichever is hit first.
+/// Only a pure depth budget gives reproducible moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchBudget {
+    pub max_depth: u32,
+    pub time: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+impl Default for SearchBudget {
+    fn default() -> Self {
+        Self::depth(3)
+    }
+}
+
+impl SearchBudget {
+    pub fn depth(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            time: None,
+            nodes: None,
+        }
+    }
+
+    /// Deepen until the time runs out
+    pub fn time(time: Duration) -> Self {
+        Self {
+            max_depth: u32::MAX,
+            time: Some(time),
+            nodes: None,
+        }
+    }
+
+    /// Deepen until this many nodes are visited
+    pub fn nodes(nodes: u64) -> Self {
+        Self {
+            max_depth: u32::MAX,
+            time: None,
+            nodes: Some(nodes),
+        }
+    }
+}
+
+/// How the Hard AI models its opponents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchAlgorithm {
+    /// Every opponent plays against us, searched with alpha-beta
+    #[default]
+    Paranoid,
+    /// Every player maximizes their own heuristic
+    MaxN,
+    /// Only the strongest reply of any single opponent is searched between two of our moves
+    BestReply,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchStats {
+    /// Nodes visited over all the iterations, including the aborted one
+    pub nodes: u64,
+    /// Depth of the last completed iteration
+    pub depth: u32,
+    /// Expected line of play from the last completed iteration, starting with the chosen move
+    pub pv: Vec<FlowCommand>,
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    /// Stats of a move that was picked without searching
+    pub fn from_move(cmd: FlowCommand) -> Self {
+        Self {
+            pv: vec![cmd],
+            ..Default::default()
+        }
+    }
+}
+
+/// Shared state of the nodes of one search
+pub(crate) struct SearchContext {
+    pub root_player: u8,
+    pub weights: HeuristicWeights,
+    start: Instant,
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    nodes: AtomicU64,
+    aborted: AtomicBool,
+    /// Some leaf was cut by the depth limit rather than the end of the game
+    cutoff: AtomicBool,
+}
+
+impl SearchContext {
+    pub fn new(root_player: u8, budget: &SearchBudget, weights: HeuristicWeights) -> Self {
+        let start = Instant::now();
+        Self {
+            root_player,
+            weights,
+            start,
+            deadline: budget.time.map(|time| start + time),
+            node_limit: budget.nodes,
+            nodes: AtomicU64::new(0),
+            aborted: AtomicBool::new(false),
+            cutoff: AtomicBool::new(false),
+        }
+    }
+
+    /// Count a node, return `false` once the budget is spent
+    pub fn visit(&self) -> bool {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.aborted.load(Ordering::Relaxed) {
+            return false;
+        }
+        let spent = self.node_limit.is_some_and(|limit| nodes > limit)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        if spent {
+            self.aborted.store(true, Ordering::Relaxed);
+        }
+        !spent
+    }
+
+    pub fn evaluate(&self, server: &FlowServer, player: u8) -> f64 {
+        heuristic_with(server, player, &self.weights)
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    pub fn mark_cutoff(&self) {
+        self.cutoff.store(true, Ordering::Relaxed);
+    }
+
+    /// Reset before the next iteration, return whether a deeper one could see more
+    pub fn take_cutoff(&self) -> bool {
+        self.cutoff.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn stats(&self, depth: u32, pv: Vec<FlowCommand>) -> SearchStats {
+        SearchStats {
+            nodes: self.nodes(),
+            depth,
+            pv,
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
+
+/// Search results by [FlowServer::state_hash], which covers the recurrence history, so a
+/// position reached along another path only shares an entry if the same moves are legal.
+pub(crate) struct TranspositionTable<V> {
+    entries: HashMap<u64, (u32, V, Vec<FlowCommand>)>,
+}
+
+impl<V> Default for TranspositionTable<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Clone> TranspositionTable<V> {
+    /// Only a search of the same depth is reused, so the score matches a search without the table
+    pub fn probe(&self, key: u64, depth: u32) -> Option<(V, Vec<FlowCommand>)> {
+        match self.entries.get(&key) {
+            Some((d, score, pv)) if *d == depth => Some((score.clone(), pv.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn store(&mut self, key: u64, depth: u32, score: V, pv: &[FlowCommand]) {
+        self.entries.insert(key, (depth, score, pv.to_vec()));
+    }
+}
+
+/// The tables of one root move, kept from one deepening iteration to the next
+#[derive(Default)]
+pub(crate) struct RootTables {
+    pub paranoid: TranspositionTable<f64>,
+    pub max_n: TranspositionTable<Vec<f64>>,
+}
@@ -0,0 +1,353 @@
+//! Headless AI-vs-AI tournaments
+//!
+//! Every table of entrants plays on every player count and board size, and the results are
+//! folded into win rates and Elo ratings in schedule order, so a run only depends on its seed.
+
+use std::fmt::{self, Display, Formatter};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use underflow_core::{
+    CellState,
+    server::{FlowServer, FlowServerConfig},
+};
+
+use crate::{AI, Difficulty, HeuristicWeights, Playout, SearchAlgorithm};
+
+const INITIAL_ELO: f64 = 1500.0;
+const ELO_K: f64 = 32.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entrant {
+    pub name: String,
+    pub difficulty: Difficulty,
+    pub algorithm: SearchAlgorithm,
+    pub playout: Playout,
+    pub weights: HeuristicWeights,
+}
+
+impl Entrant {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self {
+            name: format!("{:?}", difficulty),
+            difficulty,
+            algorithm: SearchAlgorithm::default(),
+            playout: Playout::default(),
+            weights: HeuristicWeights::default(),
+        }
+    }
+
+    pub fn with_algorithm(mut self, algorithm: SearchAlgorithm) -> Self {
+        self.name = format!("{:?}/{:?}", self.difficulty, algorithm);
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn with_playout(mut self, playout: Playout) -> Self {
+        self.name = format!("{:?}/{:?}", self.difficulty, playout);
+        self.playout = playout;
+        self
+    }
+
+    /// The name is kept, give tuned entrants one of their own
+    pub fn with_weights(mut self, weights: HeuristicWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TournamentConfig {
+    pub entrants: Vec<Entrant>,
+    pub player_counts: Vec<u8>,
+    /// Board sizes, empty for [FlowServer::optimal_size] of each player count
+    pub sizes: Vec<u8>,
+    /// Games played by every table on every player count and size
+    pub games_per_table: usize,
+    pub seed: u64,
+    /// A game still running after this many moves is a draw
+    pub max_moves: usize,
+}
+
+impl TournamentConfig {
+    pub fn new(entrants: Vec<Entrant>) -> Self {
+        Self {
+            entrants,
+            player_counts: vec![2],
+            sizes: Vec::new(),
+            games_per_table: 10,
+            seed: 0,
+            max_moves: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameResult {
+    /// Entrant index of every player
+    pub seats: Vec<usize>,
+    pub size: u8,
+    /// The AI of move `n` is seeded with `seed + n`
+    pub seed: u64,
+    /// Winning player, `None` for a draw
+    pub winner: Option<u8>,
+    pub moves: usize,
+    /// Occupied cells of every player when the game ended
+    pub cells: Vec<usize>,
+}
+
+impl GameResult {
+    pub fn winning_entrant(&self) -> Option<usize> {
+        self.winner.map(|player| self.seats[player as usize])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub elo: f64,
+}
+
+impl Standing {
+    pub fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.games as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentReport {
+    /// In the order of [TournamentConfig::entrants]
+    pub standings: Vec<Standing>,
+    pub games: Vec<GameResult>,
+}
+
+impl TournamentReport {
+    pub fn average_length(&self) -> f64 {
+        if self.games.is_empty() {
+            return 0.0;
+        }
+        self.games.iter().map(|g| g.moves).sum::<usize>() as f64 / self.games.len() as f64
+    }
+}
+
+impl Display for TournamentReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>6} {:>6} {:>8} {:>8}",
+            "entrant", "games", "wins", "draws", "win rate", "elo"
+        )?;
+        let mut standings: Vec<_> = self.standings.iter().collect();
+        standings.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+        for s in standings {
+            writeln!(
+                f,
+                "{:<16} {:>6} {:>6} {:>6} {:>7.1}% {:>8.1}",
+                s.name,
+                s.games,
+                s.wins,
+                s.draws,
+                s.win_rate() * 100.0,
+                s.elo
+            )?;
+        }
+        write!(
+            f,
+            "{} games, {:.1} moves on average",
+            self.games.len(),
+            self.average_length()
+        )
+    }
+}
+
+/// All multisets of `k` entrants out of `n`, skipping the ones with a single entrant
+fn tables(n: usize, k: usize) -> Vec<Vec<usize>> {
+    fn extend(n: usize, k: usize, from: usize, table: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        if table.len() == k {
+            if table.iter().any(|&e| e != table[0]) {
+                out.push(table.clone());
+            }
+            return;
+        }
+        for e in from..n {
+            table.push(e);
+            extend(n, k, e, table, out);
+            table.pop();
+        }
+    }
+    let mut out = Vec::new();
+    extend(n, k, 0, &mut Vec::new(), &mut out);
+    out
+}
+
+struct Schedule {
+    seats: Vec<usize>,
+    size: u8,
+    seed: u64,
+}
+
+fn play(entrants: &[Entrant], game: Schedule, max_moves: usize) -> GameResult {
+    let player_count = game.seats.len() as u8;
+    let mut result = GameResult {
+        seats: game.seats,
+        size: game.size,
+        seed: game.seed,
+        winner: None,
+        moves: 0,
+        cells: vec![0; player_count as usize],
+    };
+    let Ok(mut server) = FlowServer::new(FlowServerConfig::new(player_count, game.size)) else {
+        return result;
+    };
+    while result.moves < max_moves {
+        if let Some(winner) = server.winning() {
+            result.winner = Some(winner);
+            break;
+        }
+        let player = server.current_player;
+        if server.phase.is_flowing() && !server.has_legal_move(player) {
+            break;
+        }
+        let entrant = &entrants[result.seats[player as usize]];
+        let seed = result.seed.wrapping_add(result.moves as u64);
+        let ai = AI::with_seed(player, entrant.difficulty, seed)
+            .with_algorithm(entrant.algorithm)
+            .with_playout(entrant.playout)
+            .with_weights(entrant.weights);
+        let Ok(cmd) = ai.make_move(&mut server) else {
+            break;
+        };
+        if server.handle(cmd).is_err() {
+            break;
+        }
+        result.moves += 1;
+    }
+    for cell in server.board.cells() {
+        if let CellState::Occupied(player) = cell {
+            result.cells[player as usize] += 1;
+        }
+    }
+    result
+}
+
+/// Pairwise Elo update, a win beats every other entrant at the table and a draw splits evenly
+fn update_elo(standings: &mut [Standing], game: &GameResult) {
+    let winner = game.winning_entrant();
+    let scale = 1.0 / (game.seats.len() - 1) as f64;
+    let mut delta = vec![0.0; standings.len()];
+    for (i, &a) in game.seats.iter().enumerate() {
+        for &b in &game.seats[i + 1..] {
+            if a == b {
+                continue;
+            }
+            let expected = 1.0 / (1.0 + 10f64.powf((standings[b].elo - standings[a].elo) / 400.0));
+            let score = match winner {
+                Some(w) if w == a => 1.0,
+                Some(w) if w == b => 0.0,
+                Some(_) => continue,
+                None => 0.5,
+            };
+            delta[a] += ELO_K * scale * (score - expected);
+            delta[b] -= ELO_K * scale * (score - expected);
+        }
+    }
+    for (standing, delta) in standings.iter_mut().zip(delta) {
+        standing.elo += delta;
+    }
+}
+
+pub fn run_tournament(config: &TournamentConfig) -> TournamentReport {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut schedule = Vec::new();
+    for &player_count in &config.player_counts {
+        let sizes = if config.sizes.is_empty() {
+            vec![FlowServer::optimal_size(player_count)]
+        } else {
+            config.sizes.clone()
+        };
+        for table in tables(config.entrants.len(), player_count as usize) {
+            for &size in &sizes {
+                for _ in 0..config.games_per_table {
+                    let mut seats = table.clone();
+                    seats.shuffle(&mut rng);
+                    schedule.push(Schedule {
+                        seats,
+                        size,
+                        seed: rng.random(),
+                    });
+                }
+            }
+        }
+    }
+
+    let games: Vec<_> = schedule
+        .into_par_iter()
+        .map(|game| play(&config.entrants, game, config.max_moves))
+        .collect();
+
+    let mut standings: Vec<_> = config
+        .entrants
+        .iter()
+        .map(|e| Standing {
+            name: e.name.clone(),
+            games: 0,
+            wins: 0,
+            draws: 0,
+            elo: INITIAL_ELO,
+        })
+        .collect();
+    for game in &games {
+        let mut seated = game.seats.clone();
+        seated.sort();
+        seated.dedup();
+        for e in seated {
+            standings[e].games += 1;
+            match game.winning_entrant() {
+                Some(w) if w == e => standings[e].wins += 1,
+                None => standings[e].draws += 1,
+                _ => {}
+            }
+        }
+        update_elo(&mut standings, game);
+    }
+
+    TournamentReport { standings, games }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tables() {
+        assert_eq!(tables(2, 2), vec![vec![0, 1]]);
+        assert_eq!(tables(3, 2).len(), 3);
+        assert_eq!(tables(2, 3), vec![vec![0, 0, 1], vec![0, 1, 1]]);
+    }
+
+    #[test]
+    fn test_tournament() {
+        let mut config = TournamentConfig::new(vec![
+            Entrant::new(Difficulty::Easy),
+            Entrant::new(Difficulty::Medium),
+        ]);
+        config.sizes = vec![4];
+        config.games_per_table = 4;
+        let report = run_tournament(&config);
+        assert_eq!(report.games.len(), 4);
+        for standing in &report.standings {
+            assert_eq!(standing.games, 4);
+        }
+        let elo: f64 = report.standings.iter().map(|s| s.elo).sum();
+        assert!((elo - 2.0 * INITIAL_ELO).abs() < 1e-6);
+        assert_eq!(run_tournament(&config), report);
+    }
+}
@@ -0,0 +1,196 @@
+//! Self-play tuning of [HeuristicWeights]
+//!
+//! SPSA: every iteration nudges all the weights up or down at random, plays the two perturbed
+//! sets against each other and moves the weights toward the one that did better. Weights are
+//! scaled multiplicatively, so they keep their sign and every weight moves by a similar fraction.
+
+use std::fmt::{self, Display, Formatter};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::tournament::{Entrant, GameResult, TournamentConfig, run_tournament};
+use crate::{Difficulty, HeuristicWeights};
+
+/// Weights never shrink below this, a weight of zero could not grow back
+const MIN_WEIGHT: f64 = 1e-3;
+
+/// With two players the balance bonus is always zero and the safety weight only scales every
+/// score alike, so no weight changes a move
+pub const MIN_PLAYERS: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TunerError {
+    /// Fewer than [MIN_PLAYERS] players
+    TooFewPlayers(u8),
+}
+
+impl Display for TunerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TunerError::TooFewPlayers(count) => write!(
+                f,
+                "Cannot tune with {} players, the weights only matter with {} or more",
+                count, MIN_PLAYERS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TunerError {}
+
+#[derive(Debug, Clone)]
+pub struct TunerConfig {
+    /// Both sides play at this difficulty, the search depth multiplies the tuning time
+    pub difficulty: Difficulty,
+    pub start: HeuristicWeights,
+    pub iterations: usize,
+    /// Games between the two perturbed sets per iteration
+    pub games: usize,
+    /// At least [MIN_PLAYERS]
+    pub player_count: u8,
+    /// Board size, [underflow_core::server::FlowServer::optimal_size] if not set
+    pub size: Option<u8>,
+    pub seed: u64,
+    pub max_moves: usize,
+    /// Fraction a weight is nudged by in the first iteration
+    pub perturbation: f64,
+    /// Fraction a weight moves by in the first iteration when one side wins every game
+    pub step: f64,
+}
+
+impl TunerConfig {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self {
+            difficulty,
+            start: HeuristicWeights::default(),
+            iterations: 50,
+            games: 20,
+            player_count: MIN_PLAYERS,
+            size: None,
+            seed: 0,
+            max_moves: 300,
+            perturbation: 0.2,
+            step: 0.3,
+        }
+    }
+}
+
+fn scaled(weights: &HeuristicWeights, delta: &[f64], fraction: f64) -> HeuristicWeights {
+    let mut scaled = *weights;
+    for (key, delta) in HeuristicWeights::KEYS.iter().zip(delta) {
+        let value = weights.get(key).unwrap() * (1.0 + fraction * delta);
+        scaled.set(key, value.max(MIN_WEIGHT));
+    }
+    scaled
+}
+
+/// A won game scores 1, an unfinished one the share of the occupied cells, so draws still count
+fn score(game: &GameResult, entrant: usize) -> f64 {
+    if let Some(winner) = game.winning_entrant() {
+        return if winner == entrant { 1.0 } else { 0.0 };
+    }
+    let total = game.cells.iter().sum::<usize>().max(1) as f64;
+    game.seats
+        .iter()
+        .zip(&game.cells)
+        .filter(|&(&seat, _)| seat == entrant)
+        .map(|(_, &cells)| cells as f64 / total)
+        .sum()
+}
+
+/// Run the tuner, `progress` sees the weights after every iteration
+pub fn tune(
+    config: &TunerConfig,
+    mut progress: impl FnMut(usize, &HeuristicWeights),
+) -> Result<HeuristicWeights, TunerError> {
+    if config.player_count < MIN_PLAYERS {
+        return Err(TunerError::TooFewPlayers(config.player_count));
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut weights = config.start;
+    for iteration in 0..config.iterations {
+        // The usual SPSA decay of the gains
+        let k = (iteration + 1) as f64;
+        let perturbation = config.perturbation / k.powf(0.101);
+        let step = config.step / k.powf(0.602);
+
+        let delta: Vec<f64> = HeuristicWeights::KEYS
+            .iter()
+            .map(|_| if rng.random() { 1.0 } else { -1.0 })
+            .collect();
+        let entrant = |name: &str, weights| Entrant {
+            name: name.to_string(),
+            ..Entrant::new(config.difficulty).with_weights(weights)
+        };
+        let mut tournament = TournamentConfig::new(vec![
+            entrant("plus", scaled(&weights, &delta, perturbation)),
+            entrant("minus", scaled(&weights, &delta, -perturbation)),
+        ]);
+        tournament.player_counts = vec![config.player_count];
+        tournament.sizes = config.size.into_iter().collect();
+        tournament.games_per_table = config.games;
+        tournament.seed = rng.random();
+        tournament.max_moves = config.max_moves;
+
+        let report = run_tournament(&tournament);
+        let [plus, minus]: [f64; 2] =
+            [0, 1].map(|e| report.games.iter().map(|game| score(game, e)).sum());
+        let games = report.games.len().max(1) as f64;
+        weights = scaled(&weights, &delta, step * (plus - minus) / games);
+        progress(iteration, &weights);
+    }
+    Ok(weights)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AI;
+    use underflow_core::server::{FlowServer, FlowServerConfig};
+
+    #[test]
+    fn test_tune() {
+        let mut config = TunerConfig::new(Difficulty::Medium);
+        config.iterations = 3;
+        config.games = 2;
+        config.size = Some(5);
+        config.max_moves = 40;
+        let mut seen = 0;
+        let weights = tune(&config, |_, _| seen += 1).unwrap();
+        assert_eq!(seen, 3);
+        for key in HeuristicWeights::KEYS {
+            assert!(weights.get(key).unwrap() >= MIN_WEIGHT);
+        }
+        assert_eq!(tune(&config, |_, _| {}), Ok(weights));
+        assert_eq!(weights.to_string().parse(), Ok(weights));
+
+        // The tuned weights pick other moves than the defaults in some random position
+        let mut changed = false;
+        for game in 0..5 {
+            let mut server = FlowServer::new(FlowServerConfig::new(3, 5)).unwrap();
+            for seed in 0..60 {
+                let player = server.current_player;
+                let ai = |difficulty| AI::with_seed(player, difficulty, game * 100 + seed);
+                let Ok(random) = ai(Difficulty::Easy).make_move(&mut server) else {
+                    break;
+                };
+                if server.phase.is_flowing() {
+                    let default = ai(Difficulty::Medium).make_move(&mut server);
+                    let tuned = ai(Difficulty::Medium)
+                        .with_weights(weights)
+                        .make_move(&mut server);
+                    changed |= tuned.unwrap() != default.unwrap();
+                }
+                server.handle(random).unwrap();
+                if server.winning().is_some() {
+                    break;
+                }
+            }
+        }
+        assert!(changed);
+
+        config.player_count = 2;
+        assert_eq!(tune(&config, |_, _| {}), Err(TunerError::TooFewPlayers(2)));
+    }
+}
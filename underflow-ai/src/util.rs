@@ -15,24 +15,22 @@ pub enum OperationError {
 
 pub fn handle_command(
     server: &mut FlowServer,
-    player_id: u8,
+    _player_id: u8,
     cmd: FlowCommand,
 ) -> Result<(), OperationError> {
-    server
-        .handle(cmd)
-        .map_err(|e| OperationError::ServerError(e))
+    server.handle(cmd).map_err(OperationError::ServerError)
 }
 
 pub fn try_handle_command(
     server: &FlowServer,
-    player_id: u8,
+    _player_id: u8,
     cmd: FlowCommand,
 ) -> Result<FlowServer, OperationError> {
-    let mut server_clone = server.clone();
+    let mut server_clone = server.fork();
     server_clone
         .handle(cmd)
         .map(|_| server_clone)
-        .map_err(|e| OperationError::ServerError(e))
+        .map_err(OperationError::ServerError)
 }
 
 // ========================
@@ -41,82 +39,32 @@ pub fn try_handle_command(
 
 /// Get all valid commands for a player based on the current game phase
 pub fn get_valid_commands(server: &FlowServer, player_id: u8) -> Vec<FlowCommand> {
-    match server.phase {
-        GamePhase::Filling => get_filling_commands(server, player_id),
-        GamePhase::Flowing => get_flowing_commands(server, player_id),
-    }
-}
-
-/// Get all filling commands for a player during the filling phase
-fn get_filling_commands(server: &FlowServer, player_id: u8) -> Vec<FlowCommand> {
-    let size = server.board.size();
-    (0..size)
-        .flat_map(|x| (0..size).map(move |y| (x, y)))
-        .filter(|&(x, y)| server.board.get(x, y) == CellState::Empty)
-        .map(|(x, y)| FlowCommand::SetOccupied {
-            player: player_id,
-            x,
-            y,
-        })
+    server
+        .legal_moves(player_id)
+        .into_iter()
+        .filter(|cmd| !is_redundant_flow(server, cmd))
         .collect()
 }
 
-/// 流动阶段可用的命令
-fn get_flowing_commands(server: &FlowServer, player_id: u8) -> Vec<FlowCommand> {
-    let mut commands = Vec::new();
-    let size = server.board.size();
-
-    // 添加流动命令
-    for i in 0..size {
-        if server.board.can_flow_x(i) {
-            commands.push(FlowCommand::FlowX {
-                player: player_id,
-                y: i,
-                positive: true,
-            });
-            commands.push(FlowCommand::FlowX {
-                player: player_id,
-                y: i,
-                positive: false,
-            });
-        }
-
-        if server.board.can_flow_y(i) {
-            commands.push(FlowCommand::FlowY {
-                player: player_id,
-                x: i,
-                positive: true,
-            });
-            commands.push(FlowCommand::FlowY {
-                player: player_id,
-                x: i,
-                positive: false,
-            });
-        }
-    }
-
-    // 添加锚点操作命令
-    let anchor_positions = get_valid_anchor_positions(&server.board);
-    for (x, y) in anchor_positions {
-        commands.push(FlowCommand::SetAnchor {
-            player: player_id,
-            x,
-            y,
-        });
+/// 环绕模式下，只有两个格子的行/列向两个方向流动结果相同，负方向是多余的
+pub fn is_redundant_flow(server: &FlowServer, cmd: &FlowCommand) -> bool {
+    let board = &server.board;
+    let same_both_ways = |lane_len: usize| server.rules().flow.is_wrap() && lane_len <= 2;
+    match *cmd {
+        FlowCommand::FlowX {
+            y, positive: false, ..
+        } => same_both_ways(board.row_lane(y).len()),
+        FlowCommand::FlowY {
+            x, positive: false, ..
+        } => same_both_ways(board.col_lane(x).len()),
+        _ => false,
     }
-
-    // 用try_handle_command来过滤掉无效的命令
-    commands
-        .into_iter()
-        .filter(|cmd| try_handle_command(server, player_id, cmd.clone()).is_ok())
-        .collect()
 }
 
 /// 获取所有有效的锚点位置
 pub fn get_valid_anchor_positions(board: &Board) -> Vec<(u8, u8)> {
-    let size = board.size();
-    (0..size)
-        .flat_map(|x| (0..size).map(move |y| (x, y)))
+    board
+        .positions()
         .filter(|&(x, y)| matches!(board.get(x, y), CellState::Neutral))
         .collect()
 }
@@ -125,16 +73,16 @@ pub fn get_valid_anchor_positions(board: &Board) -> Vec<(u8, u8)> {
 // UTILITY FUNCTIONS
 // ========================
 
-pub fn evaluate_filling_position(size: i32, x: i32, y: i32) -> i32 {
+pub fn evaluate_filling_position(width: i32, height: i32, x: i32, y: i32) -> i32 {
     let left_distance = x;
-    let right_distance = size - 1 - x;
+    let right_distance = width - 1 - x;
     let top_distance = y;
-    let bottom_distance = size - 1 - y;
+    let bottom_distance = height - 1 - y;
 
     let left = left_distance.min(right_distance);
     let top = top_distance.min(bottom_distance);
 
-    left.min(top) as i32
+    left.min(top)
 }
 
 pub trait GameOverCheck {
@@ -147,18 +95,15 @@ impl GameOverCheck for FlowServer {
         // 采用遍历棋盘的方法，检查棋盘上剩余的玩家数量
         // 如果玩家数量大于1，则游戏未结束
         let board = &self.board;
-        let size = board.size();
         let mut players = HashSet::new();
 
-        for x in 0..size {
-            for y in 0..size {
-                // 用hashset来记录玩家
-                if let CellState::Occupied(player_id) = board.get(x, y) {
-                    players.insert(player_id);
-                }
-                if players.len() > 1 {
-                    return false; // 还有多个玩家，游戏未结束
-                }
+        for (x, y) in board.positions() {
+            // 用hashset来记录玩家
+            if let CellState::Occupied(player_id) = board.get(x, y) {
+                players.insert(player_id);
+            }
+            if players.len() > 1 {
+                return false; // 还有多个玩家，游戏未结束
             }
         }
         true // 只剩下一个玩家，游戏结束
@@ -0,0 +1,251 @@
+//! Terminal front end, for machines where the GUI cannot run
+//!
+//! ```text
+//! underflow-cli play [--seats human,hard] [--size 6 | --width 6 --height 4] [--rule anchors=2] [--wrap] [--save game.txt]
+//! underflow-cli replay game.txt [--step] [--analyze]
+//! underflow-cli tournament [--entrants easy,medium,hard] [--players 2,3] [--sizes 6,7] [--games 10] [--seed 0]
+//! underflow-cli tune [--ai medium] [--iterations 50] [--games 20] [--output medium.profile]
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use underflow_ai::{Difficulty, HeuristicWeights, SearchBudget, analysis::Analysis};
+use underflow_core::{
+    Board, BoardShape,
+    rules::Rules,
+    server::{FlowServer, FlowServerConfig},
+};
+
+mod play;
+mod replay;
+mod tournament;
+mod tune;
+
+const USAGE: &str = "\
+Usage:
+  underflow-cli play [options]
+  underflow-cli replay <record> [--step] [--analyze]
+  underflow-cli tournament [options]
+  underflow-cli tune [options]
+
+Play options:
+  --seats <seat,...>    One seat per player: human, easy, medium, hard or mcts
+                        (default: human,human)
+  --size <n>            Square board size (default depends on the player count)
+  --width <n>           Board width, use with --height
+  --height <n>          Board height, use with --width
+  --rule <key>=<value>  Toggle a house rule, e.g. anchors=2, recurrence=1, filling=forward
+  --wrap                Wrap-around flow variant
+  --save <path>         Write the game record when the game ends
+  --seed <n>            Seed the AI for a reproducible game
+  --think <ms>          Time the hard and mcts AI may search per move (default: depth 3,
+                        2000 iterations for mcts)
+  --iterations <n>      Nodes the hard AI searches or iterations the mcts AI runs per move
+  --profile <path>      Heuristic weights of every AI seat, as written by `tune`
+
+Replay options:
+  --step                Wait for Enter between moves
+  --analyze             Compare every move with the best one the hard AI finds
+
+Tournament options:
+  --entrants <ai,...>   AI difficulties to pit against each other (default: easy,medium,hard),
+                        hard can pick a search as hard:paranoid, hard:maxn or hard:best-reply,
+                        mcts a playout as mcts:random or mcts:heuristic,
+                        and any AI weights from a profile as medium@<path>
+  --players <n,...>     Player counts (default: 2)
+  --sizes <n,...>       Board sizes (default depends on the player count)
+  --games <n>           Games per table, player count and size (default: 10)
+  --seed <n>            RNG seed (default: 0)
+  --max-moves <n>       Moves before a game counts as a draw (default: 500)
+
+Tune options:
+  --ai <ai>             AI difficulty both sides play at (default: medium)
+  --from <path>         Profile to start from (default: the built-in weights)
+  --iterations <n>      SPSA iterations (default: 50)
+  --games <n>           Games per iteration (default: 20)
+  --players <n>         Player count, at least 3 (default: 3)
+  --size <n>            Board size (default depends on the player count)
+  --seed <n>            RNG seed (default: 0)
+  --max-moves <n>       Moves before a game counts as a draw (default: 300)
+  --output <path>       Write the profile here instead of printing it";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seat {
+    Human,
+    AI(Difficulty),
+}
+
+impl FromStr for Seat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "human" => Seat::Human,
+            "easy" => Seat::AI(Difficulty::Easy),
+            "medium" => Seat::AI(Difficulty::Medium),
+            "hard" => Seat::AI(Difficulty::Hard),
+            "mcts" => Seat::AI(Difficulty::Mcts),
+            _ => bail!("Unknown seat `{}`", s),
+        })
+    }
+}
+
+impl Display for Seat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Seat::Human => write!(f, "Human"),
+            Seat::AI(difficulty) => write!(f, "AI {:?}", difficulty),
+        }
+    }
+}
+
+/// Board with column and row indices, which are what the move commands refer to
+pub fn render_board(board: &Board) -> String {
+    let mut out = String::from("    ");
+    for x in 0..board.width() {
+        out += &format!("{:<3}", x);
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    for (y, row) in board.to_string().lines().enumerate() {
+        out += &format!("{:>2}  {}\n", y, row.trim_end());
+    }
+    out
+}
+
+pub fn print_status(server: &FlowServer) {
+    println!("{}", render_board(&server.board));
+    println!(
+        "Phase: {:?}, player {} to move",
+        server.phase, server.current_player
+    );
+}
+
+/// The `count` best moves, with the heuristic components of the position each leads to
+pub fn print_analysis(analysis: &Analysis, count: usize) {
+    for m in analysis.moves.iter().take(count) {
+        let line: Vec<_> = m.pv.iter().map(|cmd| cmd.to_string()).collect();
+        println!(
+            "  {:<14} score {:>7.2}  (safety {:.1}, balance {:.1} x {:.1})  line: {}",
+            m.cmd.to_string(),
+            m.score,
+            m.breakdown.safety,
+            m.breakdown.balance,
+            m.breakdown.diversity,
+            line.join(", ")
+        );
+    }
+}
+
+pub fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid value `{}` for {}", value, flag))
+}
+
+fn parse_play(mut args: impl Iterator<Item = String>) -> Result<play::PlayOptions> {
+    let mut seats = vec![Seat::Human, Seat::Human];
+    let mut size = None;
+    let mut width = None;
+    let mut height = None;
+    let mut wrap = false;
+    let mut house = Vec::new();
+    let mut save = None;
+    let mut seed = None;
+    let mut budget = SearchBudget::default();
+    let mut weights = HeuristicWeights::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seats" => {
+                let value: String = parse_value(&arg, args.next())?;
+                seats = value.split(',').map(str::parse).collect::<Result<_>>()?;
+            }
+            "--size" => size = Some(parse_value::<u8>(&arg, args.next())?),
+            "--width" => width = Some(parse_value::<u8>(&arg, args.next())?),
+            "--height" => height = Some(parse_value::<u8>(&arg, args.next())?),
+            "--rule" => {
+                let value: String = parse_value(&arg, args.next())?;
+                let (key, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Expected <key>=<value>, got `{}`", value))?;
+                house.push((key.to_string(), value.to_string()));
+            }
+            "--wrap" => wrap = true,
+            "--save" => save = Some(parse_value::<String>(&arg, args.next())?),
+            "--seed" => seed = Some(parse_value::<u64>(&arg, args.next())?),
+            "--think" => {
+                budget = SearchBudget::time(Duration::from_millis(parse_value(&arg, args.next())?));
+            }
+            "--iterations" => budget = SearchBudget::nodes(parse_value(&arg, args.next())?),
+            "--profile" => {
+                weights = HeuristicWeights::load(parse_value::<String>(&arg, args.next())?)?
+            }
+            _ => bail!("Unknown option `{}`\n\n{}", arg, USAGE),
+        }
+    }
+
+    let mut rules = if wrap {
+        Rules::wrap()
+    } else {
+        Rules::default()
+    };
+    for (key, value) in house {
+        if !rules.set(&key, &value) {
+            bail!("Unknown rule `{}={}`", key, value);
+        }
+    }
+    let player_count = seats.len() as u8;
+    let shape = match (width, height) {
+        (Some(_), _) | (_, Some(_)) if size.is_some() => {
+            bail!("--size does not go with --width and --height")
+        }
+        (Some(width), Some(height)) => BoardShape::rect(width, height),
+        (None, None) => {
+            BoardShape::square(size.unwrap_or_else(|| FlowServer::optimal_size(player_count)))
+        }
+        _ => bail!("--width and --height go together"),
+    };
+    Ok(play::PlayOptions {
+        config: FlowServerConfig {
+            player_count,
+            shape,
+            rules,
+        },
+        seats,
+        save,
+        seed,
+        budget,
+        weights,
+    })
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("play") => play::run(parse_play(args)?),
+        Some("replay") => {
+            let path = args.next().context(USAGE)?;
+            let mut step = false;
+            let mut analyze = false;
+            for arg in args {
+                match arg.as_str() {
+                    "--step" => step = true,
+                    "--analyze" => analyze = true,
+                    _ => bail!("Unknown option `{}`\n\n{}", arg, USAGE),
+                }
+            }
+            replay::run(&path, step, analyze)
+        }
+        Some("tournament") => tournament::run(args),
+        Some("tune") => tune::run(args),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => bail!("{}", USAGE),
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Result, anyhow};
+use underflow_ai::{AI, Difficulty, HeuristicWeights, SearchBudget};
+use underflow_core::{
+    protocol::FlowCommand,
+    record::GameRecord,
+    server::{FlowServer, FlowServerConfig},
+};
+
+use crate::{Seat, print_analysis, print_status};
+
+const HELP: &str = "\
+Moves:
+  fill <x> <y>          Occupy an empty cell while filling
+  flow x <y> +|-        Flow row y to the right (+) or left (-)
+  flow y <x> +|-        Flow column x down (+) or up (-)
+  anchor <x> <y>        Anchor a Neutral cell
+Other commands:
+  hint [n]              Show the n best moves the hard AI finds (default: 3)
+  undo, redo            Step back to or forward from your previous turn
+  save <path>           Write the game record so far
+  help, quit";
+
+pub struct PlayOptions {
+    pub config: FlowServerConfig,
+    pub seats: Vec<Seat>,
+    /// Record written when the game ends
+    pub save: Option<String>,
+    /// The AI of move `n` is seeded with `seed + n`, random if not set
+    pub seed: Option<u64>,
+    pub budget: SearchBudget,
+    pub weights: HeuristicWeights,
+}
+
+fn record(server: &FlowServer, seats: &[Seat]) -> GameRecord {
+    GameRecord::from_server(server, seats.iter().map(Seat::to_string).collect())
+}
+
+fn save(server: &FlowServer, seats: &[Seat], path: &str) -> Result<()> {
+    std::fs::write(path, record(server, seats).to_string())?;
+    println!("Saved to {}", path);
+    Ok(())
+}
+
+fn is_human(seats: &[Seat], server: &FlowServer) -> bool {
+    seats[server.current_player as usize] == Seat::Human
+}
+
+pub fn run(options: PlayOptions) -> Result<()> {
+    let PlayOptions {
+        config,
+        seats,
+        save: save_path,
+        seed,
+        budget,
+        weights,
+    } = options;
+    let mut server = FlowServer::new(config)?;
+    let mut lines = io::stdin().lock().lines();
+
+    loop {
+        if let Some(winner) = server.winning() {
+            print_status(&server);
+            println!("Player {} ({}) wins!", winner, seats[winner as usize]);
+            break;
+        }
+        let player = server.current_player;
+        if server.phase.is_flowing() && !server.has_legal_move(player) {
+            print_status(&server);
+            println!("Player {} has no legal move, game over", player);
+            break;
+        }
+
+        if let Seat::AI(difficulty) = seats[player as usize] {
+            let ai = match seed {
+                Some(seed) => {
+                    let seed = seed.wrapping_add(server.commands().len() as u64);
+                    AI::with_seed(player, difficulty, seed)
+                }
+                None => AI::new(player, difficulty),
+            };
+            let (cmd, stats) = ai
+                .with_budget(budget)
+                .with_weights(weights)
+                .search(&mut server)
+                .map_err(|e| anyhow!("AI of player {} failed: {:?}", player, e))?;
+            server.handle(cmd)?;
+            if stats.depth > 0 {
+                println!(
+                    "{}  (depth {}, {} nodes, {:.2?})",
+                    cmd, stats.depth, stats.nodes, stats.elapsed
+                );
+            } else {
+                println!("{}", cmd);
+            }
+            continue;
+        }
+
+        print_status(&server);
+        print!("player {}> ", player);
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let line = line.trim();
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => {}
+            ["help"] => println!("{}", HELP),
+            ["quit" | "exit"] => break,
+            ["undo"] => {
+                if server.undo().is_none() {
+                    println!("Nothing to undo");
+                }
+                // Skip back over the AI turns as well
+                while !is_human(&seats, &server) && server.undo().is_some() {}
+            }
+            ["redo"] => {
+                if server.redo().is_none() {
+                    println!("Nothing to redo");
+                }
+                while !is_human(&seats, &server) && server.redo().is_some() {}
+            }
+            ["save", path] => save(&server, &seats, path)?,
+            ["hint", count @ ..] if count.len() <= 1 => {
+                let Ok(count) = count.first().map_or(Ok(3), |n| n.parse()) else {
+                    println!("Usage: hint [n]");
+                    continue;
+                };
+                let seed = server.commands().len() as u64;
+                let analysis = AI::with_seed(player, Difficulty::Hard, seed)
+                    .with_budget(budget)
+                    .with_weights(weights)
+                    .analyze(&server);
+                print_analysis(&analysis, count);
+            }
+            _ => match FlowCommand::parse_move(player, line) {
+                Ok(cmd) => {
+                    if let Err(e) = server.handle(cmd) {
+                        println!("Rejected: {}", e);
+                    }
+                }
+                Err(e) => println!("{}, type `help` for the commands", e),
+            },
+        }
+    }
+
+    if let Some(path) = save_path {
+        save(&server, &seats, &path)?;
+    }
+    Ok(())
+}
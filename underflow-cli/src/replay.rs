@@ -0,0 +1,59 @@
+use std::io::{self, BufRead};
+
+use anyhow::{Context, Result};
+use underflow_ai::{AI, Difficulty};
+use underflow_core::{protocol::FlowCommand, record::GameRecord, server::FlowServer};
+
+use crate::{print_analysis, print_status};
+
+/// Where the move about to be played ranks among the moves the hard AI finds
+fn print_rank(server: &FlowServer, cmd: FlowCommand) {
+    let player = server.current_player;
+    let seed = server.commands().len() as u64;
+    let analysis = AI::with_seed(player, Difficulty::Hard, seed).analyze(server);
+    let Some(rank) = analysis.moves.iter().position(|m| m.cmd == cmd) else {
+        return;
+    };
+    if rank == 0 {
+        println!("Best move by the hard AI");
+        return;
+    }
+    println!(
+        "Ranked {} of {} by the hard AI ({:.2} below the best), it would play:",
+        rank + 1,
+        analysis.moves.len(),
+        analysis.moves[0].score - analysis.moves[rank].score
+    );
+    print_analysis(&analysis, 1);
+}
+
+pub fn run(path: &str, step: bool, analyze: bool) -> Result<()> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path))?;
+    let record: GameRecord = text.parse()?;
+    for (id, seat) in record.seats.iter().enumerate() {
+        println!("Player {}: {}", id, seat);
+    }
+
+    let mut server = FlowServer::new(record.config.clone())?;
+    let mut stdin = io::stdin().lock();
+    print_status(&server);
+    for (idx, cmd) in record.commands.iter().enumerate() {
+        if step {
+            stdin.read_line(&mut String::new())?;
+        }
+        println!("#{} {}", idx + 1, cmd);
+        if analyze {
+            print_rank(&server, *cmd);
+        }
+        server
+            .handle(*cmd)
+            .with_context(|| format!("Move #{} `{}` rejected", idx + 1, cmd))?;
+        print_status(&server);
+    }
+
+    match server.winning() {
+        Some(winner) => println!("Player {} wins", winner),
+        None => println!("Game unfinished after {} moves", record.commands.len()),
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use anyhow::{Result, bail};
+use underflow_ai::{
+    Difficulty, HeuristicWeights, Playout, SearchAlgorithm,
+    tournament::{Entrant, TournamentConfig, run_tournament},
+};
+
+use crate::{Seat, USAGE, parse_value};
+
+fn parse_list<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<Vec<T>> {
+    let value: String = parse_value(flag, value)?;
+    value
+        .split(',')
+        .map(|v| parse_value(flag, Some(v.to_string())))
+        .collect()
+}
+
+/// The part after the colon of `hard:maxn` or `mcts:heuristic`
+fn parse_variant(entrant: Entrant, s: &str) -> Result<Entrant> {
+    Ok(match (entrant.difficulty, s) {
+        (Difficulty::Hard, "paranoid") => entrant.with_algorithm(SearchAlgorithm::Paranoid),
+        (Difficulty::Hard, "maxn") => entrant.with_algorithm(SearchAlgorithm::MaxN),
+        (Difficulty::Hard, "best-reply") => entrant.with_algorithm(SearchAlgorithm::BestReply),
+        (Difficulty::Mcts, "random") => entrant.with_playout(Playout::Random),
+        (Difficulty::Mcts, "heuristic") => entrant.with_playout(Playout::Heuristic),
+        _ => bail!("Unknown variant `{}` of {:?}", s, entrant.difficulty),
+    })
+}
+
+pub fn run(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut config = TournamentConfig::new(
+        [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard]
+            .into_iter()
+            .map(Entrant::new)
+            .collect(),
+    );
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--entrants" => {
+                config.entrants = Vec::new();
+                for spec in parse_list::<String>(&arg, args.next())? {
+                    let (entrant, profile) = match spec.split_once('@') {
+                        Some((entrant, profile)) => (entrant, Some(profile)),
+                        None => (spec.as_str(), None),
+                    };
+                    let (seat, variant) = match entrant.split_once(':') {
+                        Some((seat, variant)) => (seat, Some(variant)),
+                        None => (entrant, None),
+                    };
+                    let Seat::AI(difficulty) = seat.parse()? else {
+                        bail!("Only AI can enter a tournament");
+                    };
+                    let entrant = Entrant::new(difficulty);
+                    let mut entrant = match variant {
+                        Some(variant) => parse_variant(entrant, variant)?,
+                        None => entrant,
+                    };
+                    if let Some(profile) = profile {
+                        entrant = entrant.with_weights(HeuristicWeights::load(profile)?);
+                        entrant.name = spec.clone();
+                    }
+                    config.entrants.push(entrant);
+                }
+            }
+            "--players" => config.player_counts = parse_list(&arg, args.next())?,
+            "--sizes" => config.sizes = parse_list(&arg, args.next())?,
+            "--games" => config.games_per_table = parse_value(&arg, args.next())?,
+            "--seed" => config.seed = parse_value(&arg, args.next())?,
+            "--max-moves" => config.max_moves = parse_value(&arg, args.next())?,
+            _ => bail!("Unknown option `{}`\n\n{}", arg, USAGE),
+        }
+    }
+    if config.entrants.len() < 2 {
+        bail!("A tournament needs at least two entrants");
+    }
+
+    println!("{}", run_tournament(&config));
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use anyhow::{Result, bail};
+use underflow_ai::{
+    Difficulty, HeuristicWeights,
+    tuner::{TunerConfig, tune},
+};
+
+use crate::{Seat, USAGE, parse_value};
+
+pub fn run(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut config = TunerConfig::new(Difficulty::Medium);
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ai" => {
+                let Seat::AI(difficulty) = parse_value(&arg, args.next())? else {
+                    bail!("Only AI can be tuned");
+                };
+                config.difficulty = difficulty;
+            }
+            "--from" => {
+                config.start = HeuristicWeights::load(parse_value::<String>(&arg, args.next())?)?
+            }
+            "--iterations" => config.iterations = parse_value(&arg, args.next())?,
+            "--games" => config.games = parse_value(&arg, args.next())?,
+            "--players" => config.player_count = parse_value(&arg, args.next())?,
+            "--size" => config.size = Some(parse_value(&arg, args.next())?),
+            "--seed" => config.seed = parse_value(&arg, args.next())?,
+            "--max-moves" => config.max_moves = parse_value(&arg, args.next())?,
+            "--output" => output = Some(parse_value::<String>(&arg, args.next())?),
+            _ => bail!("Unknown option `{}`\n\n{}", arg, USAGE),
+        }
+    }
+
+    let weights = tune(&config, |iteration, weights| {
+        let line = HeuristicWeights::KEYS
+            .map(|key| format!("{} {:.3}", key, weights.get(key).unwrap()))
+            .join(", ");
+        println!("#{:<4} {}", iteration + 1, line);
+    })?;
+    let profile = format!(
+        "# Tuned with {:?} AI, {} players, {} iterations of {} games, seed {}\n{}",
+        config.difficulty,
+        config.player_count,
+        config.iterations,
+        config.games,
+        config.seed,
+        weights
+    );
+    match output {
+        Some(path) => {
+            std::fs::write(&path, profile)?;
+            println!("Saved to {}", path);
+        }
+        None => print!("{}", profile),
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Packed cells of boards up to 8x8, one bit per cell and mask
+
+use crate::{CellState, FlowMode};
+
+/// Bit `y * 8 + x` is the cell (x, y). An anchored cell is set in both its player's mask and
+/// `anchors`, an Empty cell is set nowhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BitBoard {
+    pub players: [u64; BitBoard::MAX_PLAYERS],
+    pub anchors: u64,
+    pub neutral: u64,
+    pub blocked: u64,
+}
+
+impl BitBoard {
+    pub const MAX_SIZE: u8 = 8;
+    pub const MAX_PLAYERS: usize = 8;
+
+    pub fn new() -> Self {
+        Self {
+            players: [0; Self::MAX_PLAYERS],
+            anchors: 0,
+            neutral: 0,
+            blocked: 0,
+        }
+    }
+
+    /// Check if a cell state can be stored, i.e. its player id fits
+    pub fn fits(state: CellState) -> bool {
+        match state {
+            CellState::Occupied(id) | CellState::Anchored(id) => (id as usize) < Self::MAX_PLAYERS,
+            _ => true,
+        }
+    }
+
+    fn bit(x: u8, y: u8) -> u64 {
+        1 << (y as u32 * 8 + x as u32)
+    }
+
+    pub fn get(&self, x: u8, y: u8) -> CellState {
+        let bit = Self::bit(x, y);
+        if self.neutral & bit != 0 {
+            return CellState::Neutral;
+        }
+        if self.blocked & bit != 0 {
+            return CellState::Blocked;
+        }
+        match self.players.iter().position(|mask| mask & bit != 0) {
+            Some(id) if self.anchors & bit != 0 => CellState::Anchored(id as u8),
+            Some(id) => CellState::Occupied(id as u8),
+            None => CellState::Empty,
+        }
+    }
+
+    /// The state must fit, see [BitBoard::fits]
+    pub fn set(&mut self, x: u8, y: u8, state: CellState) {
+        let bit = Self::bit(x, y);
+        for mask in self.masks_mut() {
+            *mask &= !bit;
+        }
+        self.blocked &= !bit;
+        match state {
+            CellState::Empty => {}
+            CellState::Neutral => self.neutral |= bit,
+            CellState::Blocked => self.blocked |= bit,
+            CellState::Occupied(id) => self.players[id as usize] |= bit,
+            CellState::Anchored(id) => {
+                self.players[id as usize] |= bit;
+                self.anchors |= bit;
+            }
+        }
+    }
+
+    /// Every mask that moves with a flow
+    fn masks_mut(&mut self) -> impl Iterator<Item = &mut u64> {
+        self.players
+            .iter_mut()
+            .chain([&mut self.anchors, &mut self.neutral])
+    }
+
+    /// Shift a whole row or column of `len` cells, which must not contain blocked cells.
+    /// `stride` is 1 for rows and 8 for columns.
+    pub fn shift(&mut self, start: u32, stride: u32, len: u32, positive: bool, mode: FlowMode) {
+        let lane = (0..len).fold(0u64, |lane, i| lane | 1 << (start + i * stride));
+        let first = 1u64 << start;
+        let last = 1u64 << (start + (len - 1) * stride);
+        for mask in self.masks_mut() {
+            let cells = *mask & lane;
+            let (moved, pushed, incoming) = if positive {
+                ((cells & !last) << stride, cells & last, first)
+            } else {
+                ((cells & !first) >> stride, cells & first, last)
+            };
+            *mask = (*mask & !lane) | moved;
+            if mode.is_wrap() && pushed != 0 {
+                *mask |= incoming;
+            }
+        }
+        if !mode.is_wrap() {
+            let incoming = if positive { first } else { last };
+            self.neutral |= incoming;
+        }
+    }
+
+    pub fn any_anchor(&self, start: u32, stride: u32, len: u32) -> bool {
+        (0..len).any(|i| self.anchors & 1 << (start + i * stride) != 0)
+    }
+
+    /// Player id -> occupied cell count, anchors excluded
+    pub fn occupied_counts(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.players
+            .iter()
+            .enumerate()
+            .map(|(id, mask)| (id as u8, (mask & !self.anchors).count_ones() as u8))
+            .filter(|&(_, count)| count > 0)
+    }
+
+    /// Neutral and anchored cells
+    pub fn unoccupied(&self) -> usize {
+        (self.neutral | self.anchors).count_ones() as usize
+    }
+}
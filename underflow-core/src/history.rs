@@ -1,31 +1,105 @@
-use crate::Board;
+use std::collections::HashMap;
+
+use crate::{Board, FlowMode, splitmix64};
 
 #[derive(Clone)]
 pub struct BoardHistory {
-    data: Vec<Vec<Board>>,
+    /// Position -> times it was pushed, looked up by the Zobrist hash of the board
+    counts: HashMap<Board, usize>,
+    /// Every pushed position, in push order
+    order: Vec<Board>,
+    mode: FlowMode,
+    /// Sum of the scrambled hashes of the pushed positions
+    hash: u64,
 }
 
 impl BoardHistory {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self::with_mode(FlowMode::Push)
+    }
+
+    /// In [FlowMode::Wrap] the board is a torus without any edge, so boards that only differ by a
+    /// translation of the whole torus are the same position.
+    pub fn with_mode(mode: FlowMode) -> Self {
+        Self {
+            counts: HashMap::new(),
+            order: Vec::new(),
+            mode,
+            hash: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Hash of how many times each position was pushed, the push order does not matter
+    pub fn zobrist(&self) -> u64 {
+        self.hash
+    }
+
+    /// The representative stored for the position of the board, only needed in [FlowMode::Wrap].
+    /// Translations are compared by hash first, only the chosen one is built.
+    fn key(&self, board: &Board) -> Board {
+        let cells = |(dx, dy): (u8, u8)| {
+            board
+                .positions()
+                .map(move |(x, y)| board.translated_get(x, y, dx, dy))
+        };
+        board
+            .positions()
+            .map(|shift| (board.translated_zobrist(shift.0, shift.1), shift))
+            .min_by(|(a_hash, a), (b_hash, b)| {
+                a_hash.cmp(b_hash).then_with(|| cells(*a).cmp(cells(*b)))
+            })
+            .map_or_else(|| board.clone(), |(_, (dx, dy))| board.translated(dx, dy))
     }
 
     pub fn is_recurrence(&self, board: &Board) -> bool {
-        if let Some(stat) = board.stat() {
-            if let Some(collection) = self.data.get(stat.total_unoccupied) {
-                return collection.contains(board);
+        self.occurrences(board) > 0
+    }
+
+    /// How many times the position of the board has been pushed
+    pub fn occurrences(&self, board: &Board) -> usize {
+        let count = match self.mode {
+            FlowMode::Push => self.counts.get(board),
+            FlowMode::Wrap => self.counts.get(&self.key(board)),
+        };
+        count.copied().unwrap_or(0)
+    }
+
+    pub fn push(&mut self, board: &Board) {
+        let key = match self.mode {
+            FlowMode::Push => board.clone(),
+            FlowMode::Wrap => self.key(board),
+        };
+        self.hash = self.hash.wrapping_add(splitmix64(key.zobrist()));
+        *self.counts.entry(key.clone()).or_insert(0) += 1;
+        self.order.push(key);
+    }
+
+    /// Remove the most recently pushed position
+    pub fn pop(&mut self) -> Option<Board> {
+        let key = self.order.pop()?;
+        self.hash = self.hash.wrapping_sub(splitmix64(key.zobrist()));
+        if let Some(count) = self.counts.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&key);
             }
         }
-        false
+        Some(key)
     }
 
-    /// Not checked, use [BoardHistory::is_recurrence] first
-    pub fn push(&mut self, board: &Board) {
-        let unoccupied = board.stat().unwrap().total_unoccupied;
-        while self.data.len() <= unoccupied {
-            self.data.push(Vec::new());
+    /// Pop boards until only the first `len` pushed ones remain
+    pub fn truncate(&mut self, len: usize) {
+        while self.len() > len {
+            self.pop();
         }
-        self.data[unoccupied].push(board.clone());
     }
 }
 
@@ -35,4 +109,47 @@ impl Default for BoardHistory {
     }
 }
 
-// TODO: tests
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CellState;
+
+    #[test]
+    fn test_history() {
+        let mut board = Board::new(3, 3);
+        for (x, y) in board.positions() {
+            board.set(x, y, CellState::Neutral);
+        }
+        board.set(0, 0, CellState::Occupied(0));
+        let mut history = BoardHistory::new();
+        history.push(&board);
+        history.push(&board);
+        assert_eq!(history.occurrences(&board), 2);
+
+        // The hash follows the cells, not the way they were set
+        let mut other = board.clone();
+        assert!(other.flow_x(0, true));
+        assert!(!history.is_recurrence(&other));
+        other.set(0, 0, CellState::Occupied(0));
+        other.set(1, 0, CellState::Neutral);
+        assert_eq!(other.zobrist(), board.zobrist());
+        assert_eq!(history.occurrences(&other), 2);
+
+        let twice = history.zobrist();
+        history.pop();
+        assert_eq!(history.occurrences(&board), 1);
+        assert_ne!(history.zobrist(), twice);
+        history.truncate(0);
+        assert!(history.is_empty());
+        assert_eq!(history.zobrist(), 0);
+        assert!(!history.is_recurrence(&board));
+
+        let mut history = BoardHistory::with_mode(FlowMode::Wrap);
+        history.push(&board);
+        assert!(history.is_recurrence(&board.translated(1, 2)));
+        assert_eq!(
+            board.translated_zobrist(1, 2),
+            board.translated(1, 2).zobrist()
+        );
+    }
+}
@@ -1,12 +1,19 @@
+mod bitboard;
 pub mod history;
+pub mod net;
 pub mod protocol;
+pub mod record;
+pub mod rules;
 pub mod server;
 
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use bitboard::BitBoard;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum CellState {
     /// Only exists in the filling state
@@ -14,6 +21,8 @@ pub enum CellState {
     Neutral,
     Occupied(u8),
     Anchored(u8),
+    /// Not part of the board
+    Blocked,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -23,6 +32,29 @@ pub enum FlowError {
     OutOfBounds,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitError {
+    /// Only [Board::MIN_PLAYERS] to [Board::MAX_PLAYERS] players are supported
+    UnsupportedPlayerCount(u8),
+
+    /// Not enough cells left for every player
+    BoardTooSmall,
+}
+
+impl Display for InitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::UnsupportedPlayerCount(count) => {
+                write!(f, "Unsupported player count {}", count)?
+            }
+            InitError::BoardTooSmall => write!(f, "Board too small")?,
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InitError {}
+
 impl CellState {
     pub fn is_anchor(&self) -> bool {
         matches!(self, CellState::Anchored(_))
@@ -45,31 +77,185 @@ pub struct BoardStat {
     pub total_unoccupied: usize,
 }
 
+/// What happens to the cell pushed off the board by a flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlowMode {
+    /// It leaves the board and a Neutral comes in on the other side
+    #[default]
+    Push,
+    /// It comes back on the other side, i.e. the line is rotated
+    Wrap,
+}
+
+impl FlowMode {
+    pub fn is_wrap(&self) -> bool {
+        matches!(self, FlowMode::Wrap)
+    }
+}
+
+/// Dimensions and holes of a board
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardShape {
+    pub width: u8,
+    pub height: u8,
+    /// Cells that are not part of the board, flows skip over them
+    pub blocked: Vec<(u8, u8)>,
+}
+
+impl BoardShape {
+    pub fn square(size: u8) -> Self {
+        Self::rect(size, size)
+    }
+
+    pub fn rect(width: u8, height: u8) -> Self {
+        Self {
+            width,
+            height,
+            blocked: Vec::new(),
+        }
+    }
+
+    pub fn with_blocked(mut self, blocked: Vec<(u8, u8)>) -> Self {
+        self.blocked = blocked;
+        self
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+}
+
+/// Storage of the cells, boards up to 8x8 are packed
+#[derive(Debug, Clone)]
+enum Cells {
+    Grid(Vec<Vec<CellState>>),
+    Packed(BitBoard),
+}
+
+#[derive(Debug, Clone)]
 /// # The Game Board
 ///
 /// |     Axis     |  Origin  |  Index  |
 /// |--------------|----------|---------|
 /// | +x: →, +y: ↓ | Top Left | 0-based |
 pub struct Board {
-    cells: Vec<Vec<CellState>>,
-    size: u8,
+    cells: Cells,
+    width: u8,
+    height: u8,
+    /// Zobrist hash of the cells, kept up to date by [Board::set]
+    hash: u64,
+}
+
+/// Zobrist key of a cell state at a position, Empty cells have no key so a new board hashes to 0
+fn zobrist_key(x: u8, y: u8, state: CellState) -> u64 {
+    let code = match state {
+        CellState::Empty => return 0,
+        CellState::Neutral => 1,
+        CellState::Blocked => 2,
+        CellState::Occupied(id) => 3 + 2 * id as u64,
+        CellState::Anchored(id) => 4 + 2 * id as u64,
+    };
+    splitmix64(((x as u64) << 8 | y as u64) << 16 | code)
+}
+
+/// Scramble the bits of a small index into a hash key
+fn splitmix64(index: u64) -> u64 {
+    let mut z = index.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        if self.width != other.width || self.height != other.height || self.hash != other.hash {
+            return false;
+        }
+        match (&self.cells, &other.cells) {
+            (Cells::Packed(a), Cells::Packed(b)) => a == b,
+            (Cells::Grid(a), Cells::Grid(b)) => a == b,
+            _ => self
+                .positions()
+                .all(|(x, y)| self.get(x, y) == other.get(x, y)),
+        }
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
 }
 
 impl Board {
-    pub fn new(size: u8) -> Self {
-        let cells = vec![vec![CellState::Empty; size as usize]; size as usize];
-        Self { cells, size }
+    pub const MIN_PLAYERS: u8 = 2;
+    pub const MAX_PLAYERS: u8 = 8;
+
+    pub fn new(width: u8, height: u8) -> Self {
+        let cells = if width <= BitBoard::MAX_SIZE && height <= BitBoard::MAX_SIZE {
+            Cells::Packed(BitBoard::new())
+        } else {
+            Cells::Grid(vec![
+                vec![CellState::Empty; height as usize];
+                width as usize
+            ])
+        };
+        Self {
+            cells,
+            width,
+            height,
+            hash: 0,
+        }
+    }
+
+    /// Empty board with the blocked cells of the shape, blocked cells out of range are ignored
+    pub fn from_shape(shape: &BoardShape) -> Self {
+        let mut board = Self::new(shape.width, shape.height);
+        for &(x, y) in &shape.blocked {
+            if x < shape.width && y < shape.height {
+                board.set(x, y, CellState::Blocked);
+            }
+        }
+        board
     }
 
     /// No size check
     pub fn get(&self, x: u8, y: u8) -> CellState {
-        self.cells[x as usize][y as usize]
+        match &self.cells {
+            Cells::Grid(cells) => cells[x as usize][y as usize],
+            Cells::Packed(bits) => bits.get(x, y),
+        }
     }
 
     /// No size check
     pub fn set(&mut self, x: u8, y: u8, state: CellState) {
-        self.cells[x as usize][y as usize] = state;
+        self.hash ^= zobrist_key(x, y, self.get(x, y)) ^ zobrist_key(x, y, state);
+        if !BitBoard::fits(state) {
+            self.unpack();
+        }
+        match &mut self.cells {
+            Cells::Grid(cells) => cells[x as usize][y as usize] = state,
+            Cells::Packed(bits) => bits.set(x, y, state),
+        }
+    }
+
+    /// Switch to the grid storage, which takes any player id
+    fn unpack(&mut self) {
+        if let Cells::Packed(_) = self.cells {
+            self.cells = Cells::Grid(self.to_cells());
+        }
+    }
+
+    /// Check if the cells are stored as bitboards
+    pub fn is_packed(&self) -> bool {
+        matches!(self.cells, Cells::Packed(_))
+    }
+
+    /// Check if the cell is in range and not blocked
+    pub fn contains(&self, x: u8, y: u8) -> bool {
+        x < self.width && y < self.height && self.get(x, y) != CellState::Blocked
     }
 
     /// No size check
@@ -84,111 +270,308 @@ impl Board {
     #[inline]
     /// Check if all the cells in this row are not anchored, no size check
     pub fn can_flow_x(&self, y: u8) -> bool {
-        !(0..self.size).any(|x| self.get(x, y).is_anchor())
+        match &self.cells {
+            Cells::Packed(bits) => !bits.any_anchor(y as u32 * 8, 1, self.width as u32),
+            Cells::Grid(_) => !(0..self.width).any(|x| self.get(x, y).is_anchor()),
+        }
     }
 
     #[inline]
     /// Check if all the cells in this column are not anchored, no size check
     pub fn can_flow_y(&self, x: u8) -> bool {
-        !(0..self.size).any(|y| self.get(x, y).is_anchor())
+        match &self.cells {
+            Cells::Packed(bits) => !bits.any_anchor(x as u32, 8, self.height as u32),
+            Cells::Grid(_) => !(0..self.height).any(|y| self.get(x, y).is_anchor()),
+        }
     }
 
-    /// Flow the cells in the x-axis, if this row is anchored return `false`, no size check.
-    pub fn flow_x(&mut self, y: u8, positive: bool) -> bool {
-        if !self.can_flow_x(y) {
-            return false;
-        }
+    /// Non-blocked cells of the row, from left to right
+    pub fn row_lane(&self, y: u8) -> Vec<(u8, u8)> {
+        (0..self.width)
+            .map(|x| (x, y))
+            .filter(|&(x, y)| self.get(x, y) != CellState::Blocked)
+            .collect()
+    }
+
+    /// Non-blocked cells of the column, from top to bottom
+    pub fn col_lane(&self, x: u8) -> Vec<(u8, u8)> {
+        (0..self.height)
+            .map(|y| (x, y))
+            .filter(|&(x, y)| self.get(x, y) != CellState::Blocked)
+            .collect()
+    }
+
+    /// Move every cell of the lane one step, what comes in on the other side depends on the mode
+    fn shift(&mut self, lane: &[(u8, u8)], positive: bool, mode: FlowMode) {
+        let Some((&first, &last)) = lane.first().zip(lane.last()) else {
+            return;
+        };
         if positive {
-            for x in (0..self.size).rev() {
-                if x == 0 {
-                    self.set(x, y, CellState::Neutral);
-                } else {
-                    let state = self.get(x - 1, y);
-                    self.set(x, y, state);
-                }
+            let pushed = self.get(last.0, last.1);
+            for i in (1..lane.len()).rev() {
+                let state = self.get(lane[i - 1].0, lane[i - 1].1);
+                self.set(lane[i].0, lane[i].1, state);
             }
+            let incoming = if mode.is_wrap() {
+                pushed
+            } else {
+                CellState::Neutral
+            };
+            self.set(first.0, first.1, incoming);
         } else {
-            for x in 0..self.size {
-                if x == self.size - 1 {
-                    self.set(x, y, CellState::Neutral);
-                } else {
-                    let state = self.get(x + 1, y);
-                    self.set(x, y, state);
-                }
+            let pushed = self.get(first.0, first.1);
+            for i in 0..lane.len() - 1 {
+                let state = self.get(lane[i + 1].0, lane[i + 1].1);
+                self.set(lane[i].0, lane[i].1, state);
             }
+            let incoming = if mode.is_wrap() {
+                pushed
+            } else {
+                CellState::Neutral
+            };
+            self.set(last.0, last.1, incoming);
         }
-        true
     }
 
-    /// Flow the cells in the y-axis, if this row is anchored return `false`, no size check.
-    pub fn flow_y(&mut self, x: u8, positive: bool) -> bool {
-        if !self.can_flow_y(x) {
-            return false;
-        }
-        if positive {
-            for y in (0..self.size).rev() {
-                if y == 0 {
-                    self.set(x, y, CellState::Neutral);
-                } else {
-                    let state = self.get(x, y - 1);
-                    self.set(x, y, state);
-                }
+    /// Flow a row (`is_x`) or a column with the given mode, if it is anchored return `false`, no
+    /// size check.
+    pub fn flow_line(&mut self, is_x: bool, idx: u8, positive: bool, mode: FlowMode) -> bool {
+        let lane = if is_x {
+            if !self.can_flow_x(idx) {
+                return false;
             }
+            self.row_lane(idx)
         } else {
-            for y in 0..self.size {
-                if y == self.size - 1 {
-                    self.set(x, y, CellState::Neutral);
-                } else {
-                    let state = self.get(x, y + 1);
-                    self.set(x, y, state);
-                }
+            if !self.can_flow_y(idx) {
+                return false;
+            }
+            self.col_lane(idx)
+        };
+        let (start, stride, len) = if is_x {
+            (idx as u32 * 8, 1, self.width as u32)
+        } else {
+            (idx as u32, 8, self.height as u32)
+        };
+        // Without holes the lane is a plain bit shift
+        if self.is_packed() && lane.len() == len as usize {
+            let lane_hash = |board: &Self| {
+                lane.iter()
+                    .fold(0, |hash, &(x, y)| hash ^ zobrist_key(x, y, board.get(x, y)))
+            };
+            let before = lane_hash(self);
+            if let Cells::Packed(bits) = &mut self.cells {
+                bits.shift(start, stride, len, positive, mode);
             }
+            self.hash ^= before ^ lane_hash(self);
+        } else {
+            self.shift(&lane, positive, mode);
         }
         true
     }
 
+    /// Flow the cells in the x-axis, if this row is anchored return `false`, no size check.
+    pub fn flow_x(&mut self, y: u8, positive: bool) -> bool {
+        self.flow_line(true, y, positive, FlowMode::Push)
+    }
+
+    /// Flow the cells in the y-axis, if this row is anchored return `false`, no size check.
+    pub fn flow_y(&mut self, x: u8, positive: bool) -> bool {
+        self.flow_line(false, x, positive, FlowMode::Push)
+    }
+
+    /// Rotate the cells in the x-axis, if this row is anchored return `false`, no size check.
+    pub fn rotate_x(&mut self, y: u8, positive: bool) -> bool {
+        self.flow_line(true, y, positive, FlowMode::Wrap)
+    }
+
+    /// Rotate the cells in the y-axis, if this column is anchored return `false`, no size check.
+    pub fn rotate_y(&mut self, x: u8, positive: bool) -> bool {
+        self.flow_line(false, x, positive, FlowMode::Wrap)
+    }
+
+    /// The same board with every cell moved by `(dx, dy)` on the torus, no size check
+    pub fn translated(&self, dx: u8, dy: u8) -> Self {
+        let mut board = self.clone();
+        for (x, y) in self.positions() {
+            let (from_x, from_y) = self.translated_from(x, y, dx, dy);
+            board.set(x, y, self.get(from_x, from_y));
+        }
+        board
+    }
+
+    /// The cell that lands on `(x, y)` in [Board::translated]
+    fn translated_from(&self, x: u8, y: u8, dx: u8, dy: u8) -> (u8, u8) {
+        let wrap = |a: u8, b: u8, len: u8| ((a as u16 + b as u16) % len as u16) as u8;
+        (wrap(x, dx, self.width), wrap(y, dy, self.height))
+    }
+
+    /// The state of `(x, y)` in [Board::translated], without building it
+    pub(crate) fn translated_get(&self, x: u8, y: u8, dx: u8, dy: u8) -> CellState {
+        let (from_x, from_y) = self.translated_from(x, y, dx, dy);
+        self.get(from_x, from_y)
+    }
+
+    /// The Zobrist hash of [Board::translated], without building it
+    pub(crate) fn translated_zobrist(&self, dx: u8, dy: u8) -> u64 {
+        self.positions().fold(0, |hash, (x, y)| {
+            hash ^ zobrist_key(x, y, self.translated_get(x, y, dx, dy))
+        })
+    }
+
     /// Fill the board to make the empty cell count is a multiple of player count
-    pub fn init(player_count: u8, size: u8) -> Self {
-        let mut board = Self::new(size);
-        // TODO: currently only 2-4 players are supported
-        match player_count {
-            2 | 4 => {
-                if size % 2 == 0 {
-                    return board;
-                }
-                let center = size / 2;
-                board.set(center, center, CellState::Neutral);
-                board
+    ///
+    /// The neutral cells are whole orbits of the board's rotational symmetry, so the layout looks
+    /// the same from every side. The center cell is only used when no other layout works.
+    pub fn init(player_count: u8, shape: &BoardShape) -> Result<Self, InitError> {
+        if !(Self::MIN_PLAYERS..=Self::MAX_PLAYERS).contains(&player_count) {
+            return Err(InitError::UnsupportedPlayerCount(player_count));
+        }
+        let mut board = Self::from_shape(shape);
+        let total = board.cell_count();
+        let orbits = board.symmetric_orbits();
+        let (singles, others): (Vec<_>, Vec<_>) = orbits.iter().partition(|orbit| orbit.len() == 1);
+        let neutral = Self::pick_orbits(&others, total, player_count as usize)
+            .or_else(|| {
+                let all: Vec<_> = others.iter().chain(singles.iter()).copied().collect();
+                Self::pick_orbits(&all, total, player_count as usize)
+            })
+            .ok_or(InitError::BoardTooSmall)?;
+        if total - neutral.len() < player_count as usize {
+            return Err(InitError::BoardTooSmall);
+        }
+        for (x, y) in neutral {
+            board.set(x, y, CellState::Neutral);
+        }
+        Ok(board)
+    }
+
+    /// Orbits of the largest rotation (90° or 180°) that keeps the blocked cells in place,
+    /// outer diagonal orbits (i.e. the corners) first
+    fn symmetric_orbits(&self) -> Vec<Vec<(u8, u8)>> {
+        let (w, h) = (self.width, self.height);
+        let rot90 = move |(x, y): (u8, u8)| (w - 1 - y, x);
+        let rot180 = move |(x, y): (u8, u8)| (w - 1 - x, h - 1 - y);
+        let keeps_blocked = |rotate: &dyn Fn((u8, u8)) -> (u8, u8)| {
+            self.positions()
+                .filter(|&(x, y)| self.get(x, y) == CellState::Blocked)
+                .all(|pos| {
+                    let (x, y) = rotate(pos);
+                    self.get(x, y) == CellState::Blocked
+                })
+        };
+        let rotate: &dyn Fn((u8, u8)) -> (u8, u8) = if w == h && keeps_blocked(&rot90) {
+            &rot90
+        } else if keeps_blocked(&rot180) {
+            &rot180
+        } else {
+            &|pos| pos
+        };
+
+        let mut visited = vec![vec![false; h as usize]; w as usize];
+        let mut orbits = Vec::new();
+        for (x, y) in self.positions() {
+            if visited[x as usize][y as usize] || self.get(x, y) == CellState::Blocked {
+                continue;
+            }
+            let mut orbit = Vec::new();
+            let mut pos = (x, y);
+            while !visited[pos.0 as usize][pos.1 as usize] {
+                visited[pos.0 as usize][pos.1 as usize] = true;
+                orbit.push(pos);
+                pos = rotate(pos);
             }
-            3 => {
-                if size % 3 == 0 {
-                    return board;
+            orbits.push(orbit);
+        }
+        orbits.sort_by_key(|orbit| {
+            let (x, y) = orbit[0];
+            let ring = x.min(y).min(w - 1 - x).min(h - 1 - y);
+            (x != y, ring, orbit[0])
+        });
+        orbits
+    }
+
+    /// Choose the fewest cells from whole orbits so that `total - chosen` is a multiple of
+    /// `modulus`, earlier orbits are preferred on ties
+    fn pick_orbits(
+        orbits: &[&Vec<(u8, u8)>],
+        total: usize,
+        modulus: usize,
+    ) -> Option<Vec<(u8, u8)>> {
+        // residue -> (cell count, chosen orbits)
+        let mut best: Vec<Option<(usize, Vec<usize>)>> = vec![None; modulus];
+        best[0] = Some((0, Vec::new()));
+        for (idx, orbit) in orbits.iter().enumerate() {
+            let prev = best.clone();
+            for (res, entry) in prev.iter().enumerate() {
+                let Some((count, chosen)) = entry else {
+                    continue;
+                };
+                let next = (res + orbit.len()) % modulus;
+                let count = count + orbit.len();
+                if best[next].as_ref().is_none_or(|(c, _)| count < *c) {
+                    let mut chosen = chosen.clone();
+                    chosen.push(idx);
+                    best[next] = Some((count, chosen));
                 }
-                board.set(0, 0, CellState::Neutral);
-                board.set(0, size - 1, CellState::Neutral);
-                board.set(size - 1, 0, CellState::Neutral);
-                board.set(size - 1, size - 1, CellState::Neutral);
-                board
             }
-            _ => panic!("Unsupported player count"),
         }
+        best[total % modulus].take().map(|(_, chosen)| {
+            chosen
+                .into_iter()
+                .flat_map(|idx| orbits[idx].iter().copied())
+                .collect()
+        })
     }
 
-    pub fn size(&self) -> u8 {
-        self.size
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// All the positions in range, column by column
+    pub fn positions(&self) -> impl Iterator<Item = (u8, u8)> + use<> {
+        let height = self.height;
+        (0..self.width).flat_map(move |x| (0..height).map(move |y| (x, y)))
+    }
+
+    /// Every cell including the blocked ones, column by column
+    pub fn cells(&self) -> impl Iterator<Item = CellState> + '_ {
+        self.positions().map(|(x, y)| self.get(x, y))
+    }
+
+    /// Number of non-blocked cells
+    pub fn cell_count(&self) -> usize {
+        match &self.cells {
+            Cells::Packed(bits) => {
+                self.width as usize * self.height as usize - bits.blocked.count_ones() as usize
+            }
+            Cells::Grid(_) => self
+                .cells()
+                .filter(|&cell| cell != CellState::Blocked)
+                .count(),
+        }
     }
 
     pub fn stat(&self) -> Option<BoardStat> {
         if !self.is_ready() {
             return None;
         }
+        if let Cells::Packed(bits) = &self.cells {
+            return Some(BoardStat {
+                player_stat: bits.occupied_counts().collect(),
+                total_unoccupied: bits.unoccupied(),
+            });
+        }
         let mut player_stat = HashMap::new();
         let mut total_unoccupied = 0;
-        self.cells.iter().flatten().for_each(|&cell| {
+        self.cells().for_each(|cell| {
             if let Some(player) = cell.occupied_then_id() {
                 let stat = player_stat.entry(player).or_insert(0);
                 *stat += 1;
-            } else {
+            } else if cell != CellState::Blocked {
                 total_unoccupied += 1;
             }
         });
@@ -200,26 +583,45 @@ impl Board {
 
     /// Check if the board is ready to start the game, i.e. no empty cell
     pub fn is_ready(&self) -> bool {
-        self.cells
-            .iter()
-            .flatten()
-            .all(|&cell| cell != CellState::Empty)
+        match &self.cells {
+            Cells::Packed(bits) => {
+                let filled = bits
+                    .players
+                    .iter()
+                    .fold(bits.neutral | bits.blocked, |a, b| a | b);
+                let row = (1u64 << self.width) - 1;
+                (0..self.height).all(|y| (filled >> (y as u32 * 8)) & row == row)
+            }
+            Cells::Grid(_) => self.cells().all(|cell| cell != CellState::Empty),
+        }
+    }
+
+    /// Zobrist hash, equal boards have equal hashes
+    pub fn zobrist(&self) -> u64 {
+        self.hash
     }
 
-    pub fn get_cells(&self) -> &Vec<Vec<CellState>> {
-        &self.cells
+    /// A copy of the cells as `cells[x][y]`, use [Board::get] or [Board::cells] to only read them
+    pub fn to_cells(&self) -> Vec<Vec<CellState>> {
+        match &self.cells {
+            Cells::Grid(cells) => cells.clone(),
+            Cells::Packed(_) => (0..self.width)
+                .map(|x| (0..self.height).map(|y| self.get(x, y)).collect())
+                .collect(),
+        }
     }
 }
 
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        for y in 0..self.size() {
-            for x in 0..self.size() {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
                 match self.get(x, y) {
                     CellState::Empty => write!(f, "█  ")?,
                     CellState::Neutral => write!(f, "N  ")?,
                     CellState::Occupied(id) => write!(f, "O{} ", id)?,
                     CellState::Anchored(id) => write!(f, "A{} ", id)?,
+                    CellState::Blocked => write!(f, "X  ")?,
                 }
             }
             writeln!(f)?;
@@ -234,8 +636,9 @@ mod test {
 
     #[test]
     fn test_board() {
-        let mut board = Board::init(3, 7);
-        assert_eq!(board.size(), 7);
+        let mut board = Board::init(3, &BoardShape::square(7)).unwrap();
+        assert_eq!(board.width(), 7);
+        assert_eq!(board.height(), 7);
         assert_eq!(board.get(0, 0), CellState::Neutral);
         assert_eq!(board.get(0, 6), CellState::Neutral);
         assert_eq!(board.get(6, 0), CellState::Neutral);
@@ -245,11 +648,11 @@ mod test {
         assert_eq!(board.get(3, 3), CellState::Occupied(1));
         assert!(board.can_flow_x(3));
         assert!(board.can_flow_y(3));
-        assert_eq!(board.flow_x(3, true), true);
+        assert!(board.flow_x(3, true));
         assert_eq!(board.get(3, 3), CellState::Empty);
         assert_eq!(board.get(4, 3), CellState::Occupied(1));
-        assert_eq!(board.flow_y(4, true), true);
-        assert_eq!(board.flow_y(4, false), true);
+        assert!(board.flow_y(4, true));
+        assert!(board.flow_y(4, false));
         assert_eq!(board.get(4, 3), CellState::Occupied(1));
         board.set(4, 3, CellState::Anchored(1));
         assert!(!board.can_flow_x(3));
@@ -272,4 +675,143 @@ mod test {
         .join("\n");
         assert_eq!(produced, expected);
     }
+
+    #[test]
+    fn test_board_init() {
+        for player_count in Board::MIN_PLAYERS..=Board::MAX_PLAYERS {
+            for size in 6..=10 {
+                let board = Board::init(player_count, &BoardShape::square(size)).unwrap();
+                let cells = board.to_cells();
+                let empty = cells.iter().flatten().filter(|c| **c == CellState::Empty);
+                assert_eq!(empty.count() % player_count as usize, 0);
+                for x in 0..size {
+                    for y in 0..size {
+                        assert_eq!(board.get(x, y), board.get(size - 1 - y, x));
+                    }
+                }
+            }
+        }
+        let square = |size| BoardShape::square(size);
+        assert_eq!(
+            Board::init(2, &square(7)).unwrap().get(3, 3),
+            CellState::Neutral
+        );
+        assert_eq!(
+            Board::init(1, &square(7)),
+            Err(InitError::UnsupportedPlayerCount(1))
+        );
+        assert_eq!(
+            Board::init(9, &square(7)),
+            Err(InitError::UnsupportedPlayerCount(9))
+        );
+        assert_eq!(Board::init(8, &square(2)), Err(InitError::BoardTooSmall));
+    }
+
+    #[test]
+    fn test_shaped_board() {
+        let shape = BoardShape::rect(5, 3).with_blocked(vec![(1, 1), (3, 1)]);
+        for player_count in 2..=4 {
+            let board = Board::init(player_count, &shape).unwrap();
+            let empty = board
+                .positions()
+                .filter(|&(x, y)| board.get(x, y) == CellState::Empty);
+            assert_eq!(empty.count() % player_count as usize, 0);
+            for (x, y) in board.positions() {
+                assert_eq!(board.get(x, y), board.get(4 - x, 2 - y));
+            }
+        }
+
+        let mut board = Board::from_shape(&shape);
+        assert_eq!(board.cell_count(), 13);
+        assert!(!board.contains(1, 1));
+        assert!(!board.contains(5, 0));
+        board.set(0, 1, CellState::Occupied(0));
+        board.set(2, 1, CellState::Occupied(1));
+        assert!(board.flow_x(1, true));
+        assert_eq!(board.get(0, 1), CellState::Neutral);
+        assert_eq!(board.get(1, 1), CellState::Blocked);
+        assert_eq!(board.get(2, 1), CellState::Occupied(0));
+        assert_eq!(board.get(4, 1), CellState::Occupied(1));
+        assert!(board.flow_y(4, false));
+        assert_eq!(board.get(4, 0), CellState::Occupied(1));
+        assert_eq!(board.get(4, 2), CellState::Neutral);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut board = Board::from_shape(&BoardShape::rect(4, 2).with_blocked(vec![(1, 0)]));
+        board.set(0, 0, CellState::Occupied(0));
+        board.set(3, 0, CellState::Occupied(1));
+        assert!(board.rotate_x(0, true));
+        assert_eq!(board.get(0, 0), CellState::Occupied(1));
+        assert_eq!(board.get(1, 0), CellState::Blocked);
+        assert_eq!(board.get(2, 0), CellState::Occupied(0));
+        assert_eq!(board.get(3, 0), CellState::Empty);
+        assert!(board.rotate_x(0, false));
+        assert!(board.rotate_y(0, false));
+        assert_eq!(board.get(0, 0), CellState::Empty);
+        assert_eq!(board.get(0, 1), CellState::Occupied(0));
+        board.set(0, 1, CellState::Anchored(0));
+        assert!(!board.rotate_x(1, true));
+
+        // Translating wide boards must not overflow the coordinates
+        let mut board = Board::new(200, 1);
+        board.set(199, 0, CellState::Occupied(0));
+        assert_eq!(board.translated(150, 0).get(49, 0), CellState::Occupied(0));
+    }
+
+    #[test]
+    fn test_packed_board() {
+        let shapes = [
+            BoardShape::square(7),
+            BoardShape::rect(8, 5).with_blocked(vec![(2, 2), (5, 2)]),
+        ];
+        for shape in shapes {
+            let mut packed = Board::init(3, &shape).unwrap();
+            assert!(packed.is_packed());
+            let empty: Vec<_> = packed
+                .positions()
+                .filter(|&(x, y)| packed.get(x, y) == CellState::Empty)
+                .collect();
+            for (i, (x, y)) in empty.into_iter().enumerate() {
+                packed.set(x, y, CellState::Occupied(i as u8 % 3));
+            }
+            packed.set(3, 0, CellState::Anchored(1));
+            let mut grid = packed.clone();
+            grid.unpack();
+            assert!(!grid.is_packed());
+            assert_eq!(packed, grid);
+
+            for i in 0..20u8 {
+                let (is_x, positive) = (i % 3 != 0, i % 2 == 0);
+                let idx = i % if is_x { shape.height } else { shape.width };
+                let mode = if i % 5 == 0 {
+                    FlowMode::Wrap
+                } else {
+                    FlowMode::Push
+                };
+                assert_eq!(
+                    packed.flow_line(is_x, idx, positive, mode),
+                    grid.flow_line(is_x, idx, positive, mode)
+                );
+                assert_eq!(packed.to_cells(), grid.to_cells());
+                assert_eq!(packed.zobrist(), grid.zobrist());
+                let (a, b) = (packed.stat().unwrap(), grid.stat().unwrap());
+                assert_eq!(a.player_stat, b.player_stat);
+                assert_eq!(a.total_unoccupied, b.total_unoccupied);
+            }
+
+            // The hash only depends on the cells
+            let mut fresh = Board::new(shape.width, shape.height);
+            for (x, y) in packed.positions() {
+                fresh.set(x, y, packed.get(x, y));
+            }
+            assert_eq!(fresh.zobrist(), packed.zobrist());
+        }
+
+        let mut board = Board::new(4, 4);
+        board.set(0, 0, CellState::Occupied(9));
+        assert!(!board.is_packed());
+        assert_eq!(board.get(0, 0), CellState::Occupied(9));
+    }
 }
@@ -0,0 +1,405 @@
+//! Line protocol for playing a [FlowServer](crate::server::FlowServer) over TCP
+//!
+//! Every message is one line of text, only `sync` is followed by the lines of a [GameRecord].
+//!
+//! ```text
+//! > join 1
+//! < welcome 1 2
+//! < sync 4
+//! < underflow-record 1
+//! < players 2
+//! < size 6
+//! < move 0 fill 2 3
+//! < turn 1
+//! > move fill 0 0
+//! < moved 8213609641290176518 1 fill 0 0
+//! < turn 0
+//! > move fill 9 9
+//! < error index-out-of-range
+//! ```
+//!
+//! Clients send `join [seat]`, `watch`, `move <move>` in the notation of
+//! [FlowCommand::parse_move], `sync` and `quit`. The host answers a join with
+//! `welcome <seat> <players>` and a `sync`, a watch with only the `sync`,
+//! broadcasts every accepted command as `moved <zobrist> <command>`, announces the player to
+//! move with `turn <player>` once every seat is taken, and ends the game with
+//! `over <player|draw>`. The zobrist hash of the board after the move lets a client notice that
+//! it went out of sync and ask for a `sync`.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, Read, Write};
+
+use crate::{
+    protocol::{FlowCommand, FlowError},
+    record::GameRecord,
+};
+
+/// Port used when none is given
+pub const DEFAULT_PORT: u16 = 7270;
+
+/// Longest line in bytes either side reads, so a peer that never sends a line break cannot
+/// make the reader buffer without end
+pub const MAX_LINE: usize = 64 * 1024;
+
+/// Largest record in bytes a `sync` may carry, whatever line count it announces
+pub const MAX_RECORD: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    /// Take the given seat, or the first free one
+    Join(Option<u8>),
+    /// Follow the game without a seat
+    Watch,
+    /// A move of the joined seat
+    Move(FlowCommand),
+    /// Ask for the whole game again
+    Sync,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerMessage {
+    Welcome {
+        seat: u8,
+        player_count: u8,
+    },
+    /// Another client took a seat
+    Joined(u8),
+    /// A seat is free again
+    Left(u8),
+    /// Everything accepted so far
+    Sync(GameRecord),
+    /// An accepted command and the zobrist hash of the board after it
+    Moved {
+        zobrist: u64,
+        cmd: FlowCommand,
+    },
+    /// The player to move
+    Turn(u8),
+    /// The winner, none for a game nobody can finish
+    Over(Option<u8>),
+    Error(NetError),
+}
+
+/// Error replies, sent only to the client that caused them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetError {
+    /// The move was rejected by the server
+    Rejected(FlowError),
+    /// The line is not a message
+    BadRequest(String),
+    /// The asked seat is taken or there is no free seat left
+    NoSeat,
+    /// Moves need a seat
+    NotSeated,
+    /// Not every seat is taken yet
+    Waiting,
+    /// The game is over
+    Finished,
+}
+
+impl Display for NetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Rejected(e) => write!(f, "{}", e)?,
+            NetError::BadRequest(line) => write!(f, "Bad request: {:?}", line)?,
+            NetError::NoSeat => write!(f, "No free seat")?,
+            NetError::NotSeated => write!(f, "Not seated")?,
+            NetError::Waiting => write!(f, "Waiting for players")?,
+            NetError::Finished => write!(f, "Game over")?,
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NetError {}
+
+const FLOW_ERRORS: [(FlowError, &str); 7] = [
+    (FlowError::InvalidPhase, "invalid-phase"),
+    (FlowError::BlockedByAnchor, "blocked-by-anchor"),
+    (FlowError::IndexOutOfRange, "index-out-of-range"),
+    (FlowError::NotYourTurn, "not-your-turn"),
+    (FlowError::Recurrence, "recurrence"),
+    (FlowError::AlreadyOccupied, "already-occupied"),
+    (FlowError::YouAreDead, "you-are-dead"),
+];
+
+impl NetError {
+    /// Code used on the wire
+    pub fn code(&self) -> &'static str {
+        match self {
+            NetError::Rejected(e) => FLOW_ERRORS.iter().find(|(f, _)| f == e).unwrap().1,
+            NetError::BadRequest(_) => "bad-request",
+            NetError::NoSeat => "no-seat",
+            NetError::NotSeated => "not-seated",
+            NetError::Waiting => "waiting",
+            NetError::Finished => "finished",
+        }
+    }
+
+    fn from_code(code: &str, detail: &str) -> Option<Self> {
+        if let Some((e, _)) = FLOW_ERRORS.iter().find(|(_, c)| *c == code) {
+            return Some(NetError::Rejected(*e));
+        }
+        Some(match code {
+            "bad-request" => NetError::BadRequest(detail.to_string()),
+            "no-seat" => NetError::NoSeat,
+            "not-seated" => NetError::NotSeated,
+            "waiting" => NetError::Waiting,
+            "finished" => NetError::Finished,
+            _ => return None,
+        })
+    }
+}
+
+/// A line that is not a valid message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMessageError(pub String);
+
+impl Display for ParseMessageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid message: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseMessageError {}
+
+impl From<ParseMessageError> for io::Error {
+    fn from(e: ParseMessageError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// The command without the leading player id
+fn move_text(cmd: &FlowCommand) -> String {
+    let text = cmd.to_string();
+    text.split_once(' ')
+        .map_or(text.clone(), |(_, m)| m.to_string())
+}
+
+impl Display for ClientMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessage::Join(Some(seat)) => write!(f, "join {}", seat),
+            ClientMessage::Join(None) => write!(f, "join"),
+            ClientMessage::Watch => write!(f, "watch"),
+            ClientMessage::Move(cmd) => write!(f, "move {}", move_text(cmd)),
+            ClientMessage::Sync => write!(f, "sync"),
+            ClientMessage::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl ClientMessage {
+    /// Moves carry no player on the wire, the host fills in the seat of the sender
+    pub fn parse(seat: u8, s: &str) -> Result<Self, ParseMessageError> {
+        let err = || ParseMessageError(s.trim().to_string());
+        let s = s.trim();
+        let (key, value) = s.split_once(' ').unwrap_or((s, ""));
+        let value = value.trim();
+        Ok(match key {
+            "join" if value.is_empty() => ClientMessage::Join(None),
+            "join" => ClientMessage::Join(Some(value.parse().map_err(|_| err())?)),
+            "watch" if value.is_empty() => ClientMessage::Watch,
+            "move" => ClientMessage::Move(FlowCommand::parse_move(seat, value).map_err(|_| err())?),
+            "sync" if value.is_empty() => ClientMessage::Sync,
+            "quit" if value.is_empty() => ClientMessage::Quit,
+            _ => return Err(err()),
+        })
+    }
+}
+
+/// `sync` writes the record on the following lines
+impl Display for ServerMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::Welcome { seat, player_count } => {
+                write!(f, "welcome {} {}", seat, player_count)
+            }
+            ServerMessage::Joined(seat) => write!(f, "joined {}", seat),
+            ServerMessage::Left(seat) => write!(f, "left {}", seat),
+            ServerMessage::Sync(record) => {
+                let record = record.to_string();
+                write!(f, "sync {}\n{}", record.lines().count(), record.trim_end())
+            }
+            ServerMessage::Moved { zobrist, cmd } => write!(f, "moved {} {}", zobrist, cmd),
+            ServerMessage::Turn(player) => write!(f, "turn {}", player),
+            ServerMessage::Over(Some(winner)) => write!(f, "over {}", winner),
+            ServerMessage::Over(None) => write!(f, "over draw"),
+            ServerMessage::Error(NetError::BadRequest(line)) => {
+                write!(f, "error bad-request {}", line)
+            }
+            ServerMessage::Error(e) => write!(f, "error {}", e.code()),
+        }
+    }
+}
+
+impl ServerMessage {
+    /// Read the next message, `None` once the connection is closed
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let Some(line) = read_line(reader)? else {
+            return Ok(None);
+        };
+        let line = line.trim();
+        let err = || ParseMessageError(line.to_string());
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let mut values = value.split_whitespace();
+        let mut num = || {
+            values
+                .next()
+                .and_then(|v| v.parse::<u8>().ok())
+                .ok_or_else(err)
+        };
+        let message = match key {
+            "welcome" => ServerMessage::Welcome {
+                seat: num()?,
+                player_count: num()?,
+            },
+            "joined" => ServerMessage::Joined(num()?),
+            "left" => ServerMessage::Left(num()?),
+            "turn" => ServerMessage::Turn(num()?),
+            "over" if value == "draw" => ServerMessage::Over(None),
+            "over" => ServerMessage::Over(Some(num()?)),
+            "sync" => {
+                let len: usize = value.parse().map_err(|_| err())?;
+                let mut text = String::new();
+                for _ in 0..len {
+                    let line = read_line(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+                    if text.len() + line.len() >= MAX_RECORD {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Record too long",
+                        ));
+                    }
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+                let record = text
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                ServerMessage::Sync(record)
+            }
+            "moved" => {
+                let (zobrist, cmd) = value.split_once(' ').ok_or_else(err)?;
+                ServerMessage::Moved {
+                    zobrist: zobrist.parse().map_err(|_| err())?,
+                    cmd: cmd.parse().map_err(|_| err())?,
+                }
+            }
+            "error" => {
+                let (code, detail) = value.split_once(' ').unwrap_or((value, ""));
+                ServerMessage::Error(NetError::from_code(code, detail).ok_or_else(err)?)
+            }
+            _ => return Err(err().into()),
+        };
+        Ok(Some(message))
+    }
+}
+
+/// Read one line without its line break, `None` at the end of the stream. A line longer than
+/// [MAX_LINE] is an [io::ErrorKind::InvalidData] error.
+pub fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    if line.len() > MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write one message and flush it
+pub fn send(stream: &mut impl Write, message: &impl Display) -> io::Result<()> {
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::{FlowServer, FlowServerConfig};
+
+    #[test]
+    fn test_messages() {
+        let cmd = FlowCommand::FlowX {
+            player: 1,
+            y: 2,
+            positive: false,
+        };
+        for message in [
+            ClientMessage::Join(None),
+            ClientMessage::Join(Some(3)),
+            ClientMessage::Watch,
+            ClientMessage::Move(cmd),
+            ClientMessage::Sync,
+        ] {
+            assert_eq!(ClientMessage::parse(1, &message.to_string()), Ok(message));
+        }
+        assert!(ClientMessage::parse(0, "move fly").is_err());
+
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        server
+            .handle(FlowCommand::SetOccupied {
+                player: 0,
+                x: 1,
+                y: 1,
+            })
+            .unwrap();
+        let messages = [
+            ServerMessage::Welcome {
+                seat: 1,
+                player_count: 2,
+            },
+            ServerMessage::Sync(GameRecord::from_server(&server, vec!["Open".into(); 2])),
+            ServerMessage::Moved {
+                zobrist: server.board.zobrist(),
+                cmd,
+            },
+            ServerMessage::Turn(0),
+            ServerMessage::Over(None),
+            ServerMessage::Error(NetError::Rejected(FlowError::Recurrence)),
+            ServerMessage::Error(NetError::BadRequest("fly".into())),
+        ];
+        let text: String = messages.iter().map(|m| format!("{}\n", m)).collect();
+        let mut reader = text.as_bytes();
+        for message in messages {
+            assert_eq!(ServerMessage::read(&mut reader).unwrap(), Some(message));
+        }
+        assert!(ServerMessage::read(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut reader = "join\r\nsync".as_bytes();
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("join"));
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("sync"));
+        assert_eq!(read_line(&mut reader).unwrap(), None);
+
+        let long = "x".repeat(MAX_LINE + 1);
+        let err = read_line(&mut long.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let fits = "x".repeat(MAX_LINE) + "\n";
+        assert_eq!(
+            read_line(&mut fits.as_bytes()).unwrap().unwrap().len(),
+            MAX_LINE
+        );
+
+        // Lines of a sync count toward the record size, not only their own length
+        let sync = format!("sync {}\n", usize::MAX) + &"move 0 fill 0 0\n".repeat(MAX_RECORD / 8);
+        let err = ServerMessage::read(&mut sync.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FlowCommand {
     SetOccupied { player: u8, x: u8, y: u8 },
     SetAnchor { player: u8, x: u8, y: u8 },
@@ -6,6 +6,129 @@ pub enum FlowCommand {
     FlowY { player: u8, x: u8, positive: bool },
 }
 
+impl FlowCommand {
+    pub fn player(&self) -> u8 {
+        match *self {
+            Self::SetOccupied { player, .. }
+            | Self::SetAnchor { player, .. }
+            | Self::FlowX { player, .. }
+            | Self::FlowY { player, .. } => player,
+        }
+    }
+
+    /// Parse a move without the leading player id, e.g. `fill 3 4`, `flow x 2 +` or `anchor 1 1`
+    pub fn parse_move(player: u8, s: &str) -> Result<Self, ParseCommandError> {
+        let err = || ParseCommandError(s.trim().to_string());
+        let num = |t: Option<&str>| t.and_then(|t| t.parse::<u8>().ok()).ok_or_else(err);
+        let mut tokens = s.split_whitespace();
+        let cmd = match tokens.next() {
+            Some("fill") => Self::SetOccupied {
+                player,
+                x: num(tokens.next())?,
+                y: num(tokens.next())?,
+            },
+            Some("anchor") => Self::SetAnchor {
+                player,
+                x: num(tokens.next())?,
+                y: num(tokens.next())?,
+            },
+            Some("flow") => {
+                let axis = tokens.next();
+                let idx = num(tokens.next())?;
+                let positive = match tokens.next() {
+                    Some("+") => true,
+                    Some("-") => false,
+                    _ => return Err(err()),
+                };
+                match axis {
+                    Some("x") => Self::FlowX {
+                        player,
+                        y: idx,
+                        positive,
+                    },
+                    Some("y") => Self::FlowY {
+                        player,
+                        x: idx,
+                        positive,
+                    },
+                    _ => return Err(err()),
+                }
+            }
+            _ => return Err(err()),
+        };
+        if tokens.next().is_some() {
+            return Err(err());
+        }
+        Ok(cmd)
+    }
+}
+
+/// Notation: `<player> fill <x> <y>`, `<player> anchor <x> <y>` or `<player> flow <x|y> <idx> <+|->`
+impl std::fmt::Display for FlowCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = |positive: bool| if positive { '+' } else { '-' };
+        match *self {
+            Self::SetOccupied { player, x, y } => write!(f, "{} fill {} {}", player, x, y),
+            Self::SetAnchor { player, x, y } => write!(f, "{} anchor {} {}", player, x, y),
+            Self::FlowX {
+                player,
+                y,
+                positive,
+            } => write!(f, "{} flow x {} {}", player, y, sign(positive)),
+            Self::FlowY {
+                player,
+                x,
+                positive,
+            } => write!(f, "{} flow y {} {}", player, x, sign(positive)),
+        }
+    }
+}
+
+impl std::str::FromStr for FlowCommand {
+    type Err = ParseCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (player, rest) = s
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| ParseCommandError(s.to_string()))?;
+        let player = player
+            .parse()
+            .map_err(|_| ParseCommandError(s.to_string()))?;
+        Self::parse_move(player, rest)
+    }
+}
+
+/// The text that failed to parse as a [FlowCommand]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCommandError(pub String);
+
+impl std::fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid command: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseCommandError {}
+
+/// What an accepted command did, in the order it happened, see [crate::server::FlowServer::subscribe]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowEvent {
+    MoveApplied(FlowCommand),
+    /// Undone with [crate::server::FlowServer::undo], no other event follows an undo
+    MoveUndone(FlowCommand),
+    PhaseChanged(GamePhase),
+    /// An anchor left the board: replaced by a newer one, or its player is dead or eliminated
+    AnchorRemoved {
+        player: u8,
+        x: u8,
+        y: u8,
+    },
+    /// The player has no occupied cell left
+    PlayerEliminated(u8),
+    GameWon(u8),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum GamePhase {
@@ -23,7 +146,7 @@ impl GamePhase {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FlowError {
     /// e.g. SetOccupied during Flowing phase
     InvalidPhase,
@@ -0,0 +1,353 @@
+//! Plain-text game records
+//!
+//! ```text
+//! underflow-record 1
+//! players 2
+//! size 6
+//! seat 0 Human
+//! seat 1 AI Hard
+//! move 0 fill 2 3
+//! move 1 fill 0 0
+//! ```
+//!
+//! Rectangular boards use `width <w>` and `height <h>` instead of `size`, and holes are listed as
+//! `blocked <x>,<y> <x>,<y> ...`. Every rule that differs from the default gets a
+//! `rule <key> <value>` line, see [Rules::set]; `flow wrap` is short for the [Rules::wrap] preset.
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::{
+    BoardShape, InitError,
+    protocol::{FlowCommand, FlowError},
+    rules::Rules,
+    server::{FlowServer, FlowServerConfig},
+};
+
+const HEADER: &str = "underflow-record";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub config: FlowServerConfig,
+    /// Free-form seat labels indexed by player id, may be empty
+    pub seats: Vec<String>,
+    pub commands: Vec<FlowCommand>,
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    /// Missing or malformed `underflow-record <version>` line
+    InvalidHeader,
+
+    UnsupportedVersion(u32),
+
+    /// 1-based line number and the offending line
+    Syntax(usize, String),
+
+    MissingField(&'static str),
+
+    /// The recorded config cannot start a game
+    Init(InitError),
+
+    /// The command at this index was rejected while replaying
+    Rejected(usize, FlowCommand, FlowError),
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::InvalidHeader => write!(f, "Invalid record header")?,
+            RecordError::UnsupportedVersion(v) => write!(f, "Unsupported record version {}", v)?,
+            RecordError::Syntax(line, s) => write!(f, "Syntax error at line {}: {:?}", line, s)?,
+            RecordError::MissingField(field) => write!(f, "Missing field `{}`", field)?,
+            RecordError::Init(e) => write!(f, "Invalid config: {}", e)?,
+            RecordError::Rejected(idx, cmd, e) => {
+                write!(f, "Move #{} `{}` rejected: {}", idx + 1, cmd, e)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl GameRecord {
+    pub fn new(config: FlowServerConfig) -> Self {
+        Self {
+            config,
+            seats: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Capture everything accepted by the server so far
+    pub fn from_server(server: &FlowServer, seats: Vec<String>) -> Self {
+        Self {
+            config: server.config().clone(),
+            seats,
+            commands: server.commands().to_vec(),
+        }
+    }
+
+    /// Replay every command into a fresh server
+    pub fn replay(&self) -> Result<FlowServer, RecordError> {
+        self.replay_to(self.commands.len())
+    }
+
+    /// Replay the first `len` commands into a fresh server
+    pub fn replay_to(&self, len: usize) -> Result<FlowServer, RecordError> {
+        let mut server = FlowServer::new(self.config.clone()).map_err(RecordError::Init)?;
+        for (idx, cmd) in self.commands.iter().take(len).enumerate() {
+            server
+                .handle(*cmd)
+                .map_err(|e| RecordError::Rejected(idx, *cmd, e))?;
+        }
+        Ok(server)
+    }
+}
+
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "players {}", self.config.player_count)?;
+        let shape = &self.config.shape;
+        if shape.is_square() {
+            writeln!(f, "size {}", shape.width)?;
+        } else {
+            writeln!(f, "width {}", shape.width)?;
+            writeln!(f, "height {}", shape.height)?;
+        }
+        if !shape.blocked.is_empty() {
+            write!(f, "blocked")?;
+            for (x, y) in &shape.blocked {
+                write!(f, " {},{}", x, y)?;
+            }
+            writeln!(f)?;
+        }
+        for (key, value) in self.config.rules.changes() {
+            writeln!(f, "rule {} {}", key, value)?;
+        }
+        for (id, seat) in self.seats.iter().enumerate() {
+            writeln!(f, "seat {} {}", id, seat)?;
+        }
+        for cmd in &self.commands {
+            writeln!(f, "move {}", cmd)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let version = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix(HEADER))
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .ok_or(RecordError::InvalidHeader)?;
+        if version != VERSION {
+            return Err(RecordError::UnsupportedVersion(version));
+        }
+
+        let mut player_count = None;
+        let mut width = None;
+        let mut height = None;
+        let mut blocked = Vec::new();
+        let mut rules = Rules::default();
+        // `flow` picks a whole preset, so it cannot follow rules it would overwrite
+        let mut ruled = false;
+        let mut seats = Vec::new();
+        let mut commands = Vec::new();
+        for (no, line) in lines {
+            let syntax = || RecordError::Syntax(no, line.to_string());
+            let (key, value) = line.split_once(char::is_whitespace).ok_or_else(syntax)?;
+            let value = value.trim();
+            match key {
+                "players" => player_count = Some(value.parse::<u8>().map_err(|_| syntax())?),
+                "size" => {
+                    let size = value.parse::<u8>().map_err(|_| syntax())?;
+                    width = Some(size);
+                    height = Some(size);
+                }
+                "width" => width = Some(value.parse::<u8>().map_err(|_| syntax())?),
+                "height" => height = Some(value.parse::<u8>().map_err(|_| syntax())?),
+                "flow" => {
+                    if ruled {
+                        return Err(syntax());
+                    }
+                    rules = match value {
+                        "push" => Rules::default(),
+                        "wrap" => Rules::wrap(),
+                        _ => return Err(syntax()),
+                    }
+                }
+                "rule" => {
+                    let (key, value) = value.split_once(char::is_whitespace).ok_or_else(syntax)?;
+                    if !rules.set(key, value.trim()) {
+                        return Err(syntax());
+                    }
+                    ruled = true;
+                }
+                "blocked" => {
+                    for pos in value.split_whitespace() {
+                        let (x, y) = pos.split_once(',').ok_or_else(syntax)?;
+                        let x = x.parse::<u8>().map_err(|_| syntax())?;
+                        let y = y.parse::<u8>().map_err(|_| syntax())?;
+                        blocked.push((x, y));
+                    }
+                }
+                "seat" => {
+                    let (id, label) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+                    if id.parse::<usize>().ok() != Some(seats.len()) {
+                        return Err(syntax());
+                    }
+                    seats.push(label.trim().to_string());
+                }
+                "move" => commands.push(value.parse().map_err(|_| syntax())?),
+                _ => return Err(syntax()),
+            }
+        }
+
+        let shape = BoardShape::rect(
+            width.ok_or(RecordError::MissingField("width"))?,
+            height.ok_or(RecordError::MissingField("height"))?,
+        )
+        .with_blocked(blocked);
+        let config = FlowServerConfig {
+            player_count: player_count.ok_or(RecordError::MissingField("players"))?,
+            shape,
+            rules,
+        };
+        Ok(Self {
+            config,
+            seats,
+            commands,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 2)).unwrap();
+        for cmd in [
+            FlowCommand::SetOccupied {
+                player: 0,
+                x: 0,
+                y: 0,
+            },
+            FlowCommand::SetOccupied {
+                player: 1,
+                x: 1,
+                y: 0,
+            },
+            FlowCommand::SetOccupied {
+                player: 0,
+                x: 0,
+                y: 1,
+            },
+            FlowCommand::SetOccupied {
+                player: 1,
+                x: 1,
+                y: 1,
+            },
+            FlowCommand::FlowX {
+                player: 0,
+                y: 0,
+                positive: true,
+            },
+        ] {
+            server.handle(cmd).unwrap();
+        }
+
+        let record = GameRecord::from_server(&server, vec!["Human".into(), "AI Hard".into()]);
+        let text = record.to_string();
+        assert!(text.starts_with("underflow-record 1\n"));
+        assert!(text.contains("seat 1 AI Hard\n"));
+        assert!(text.contains("move 0 flow x 0 +\n"));
+
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        let replayed = parsed.replay().unwrap();
+        assert_eq!(replayed.board, server.board);
+        assert_eq!(replayed.current_player, server.current_player);
+        assert_eq!(replayed.phase, server.phase);
+    }
+
+    #[test]
+    fn test_record_empty_seat() {
+        let server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        let record = GameRecord::from_server(&server, vec!["".into(), "AI Hard".into()]);
+        let parsed: GameRecord = record.to_string().parse().unwrap();
+        assert_eq!(parsed, record);
+    }
+
+    #[test]
+    fn test_record_errors() {
+        assert!(matches!(
+            "players 2".parse::<GameRecord>(),
+            Err(RecordError::InvalidHeader)
+        ));
+        assert!(matches!(
+            "underflow-record 1\nplayers 2\nsize 4\nmove 0 flow z 1 +".parse::<GameRecord>(),
+            Err(RecordError::Syntax(4, _))
+        ));
+        let record: GameRecord = "underflow-record 1\nplayers 2\nsize 4\nmove 1 fill 0 0"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            record.replay(),
+            Err(RecordError::Rejected(0, _, FlowError::NotYourTurn))
+        ));
+    }
+
+    #[test]
+    fn test_shaped_record() {
+        let shape = BoardShape::rect(5, 3).with_blocked(vec![(1, 1), (3, 1)]);
+        let record = GameRecord::new(FlowServerConfig {
+            player_count: 3,
+            shape,
+            rules: Rules {
+                anchors_per_player: 2,
+                ..Rules::wrap()
+            },
+        });
+        let text = record.to_string();
+        assert!(text.contains("width 5\nheight 3\nblocked 1,1 3,1\nrule flow wrap\n"));
+        assert!(text.contains("rule anchors 2\nrule stuck eliminate\n"));
+        assert_eq!(text.parse::<GameRecord>().unwrap(), record);
+
+        let wrap: GameRecord = "underflow-record 1\nplayers 2\nsize 4\nflow wrap"
+            .parse()
+            .unwrap();
+        assert_eq!(wrap.config.rules, Rules::wrap());
+        let anchored: GameRecord =
+            "underflow-record 1\nplayers 2\nsize 4\nflow wrap\nrule anchors 2"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            anchored.config.rules,
+            Rules {
+                anchors_per_player: 2,
+                ..Rules::wrap()
+            }
+        );
+        assert!(matches!(
+            "underflow-record 1\nplayers 2\nsize 4\nrule anchors 2\nflow wrap"
+                .parse::<GameRecord>(),
+            Err(RecordError::Syntax(5, _))
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::FlowMode;
+
+/// Turn order during the filling phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillingOrder {
+    /// Turns go backwards, so the player who fills last moves first when flowing
+    #[default]
+    Reverse,
+    Forward,
+}
+
+/// House rules of a game, [Rules::default] is the standard game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub flow: FlowMode,
+
+    /// Anchors a player may keep on the board, placing one more turns the oldest one Neutral.
+    /// Values below 1 act as 1.
+    pub anchors_per_player: u8,
+
+    /// How many times a position may show up again before it counts as a recurrence
+    pub recurrence_limit: u8,
+
+    pub filling_order: FillingOrder,
+
+    /// Anchors of dead players turn Neutral
+    pub clear_dead_anchors: bool,
+
+    /// A player who has no legal move on their turn is eliminated
+    pub eliminate_stuck: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            flow: FlowMode::Push,
+            anchors_per_player: 1,
+            recurrence_limit: 0,
+            filling_order: FillingOrder::Reverse,
+            clear_dead_anchors: true,
+            eliminate_stuck: false,
+        }
+    }
+}
+
+impl Rules {
+    /// The wrap-around variant, nothing leaves the board so getting stuck is how players lose
+    pub fn wrap() -> Self {
+        Self {
+            flow: FlowMode::Wrap,
+            eliminate_stuck: true,
+            ..Default::default()
+        }
+    }
+
+    /// `(key, value)` pairs of the rules that differ from the default, see [Rules::set]
+    pub fn changes(&self) -> Vec<(&'static str, String)> {
+        let default = Self::default();
+        let mut changes = Vec::new();
+        if self.flow != default.flow {
+            changes.push(("flow", "wrap".to_string()));
+        }
+        if self.anchors_per_player != default.anchors_per_player {
+            changes.push(("anchors", self.anchors_per_player.to_string()));
+        }
+        if self.recurrence_limit != default.recurrence_limit {
+            changes.push(("recurrence", self.recurrence_limit.to_string()));
+        }
+        if self.filling_order != default.filling_order {
+            changes.push(("filling", "forward".to_string()));
+        }
+        if self.clear_dead_anchors != default.clear_dead_anchors {
+            changes.push(("dead-anchors", "keep".to_string()));
+        }
+        if self.eliminate_stuck != default.eliminate_stuck {
+            changes.push(("stuck", "eliminate".to_string()));
+        }
+        changes
+    }
+
+    /// Set a rule from its textual form, return `false` if the key or the value is unknown
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        match (key, value) {
+            ("flow", "push") => self.flow = FlowMode::Push,
+            ("flow", "wrap") => self.flow = FlowMode::Wrap,
+            ("anchors", n) => match n.parse() {
+                Ok(n) => self.anchors_per_player = n,
+                Err(_) => return false,
+            },
+            ("recurrence", n) => match n.parse() {
+                Ok(n) => self.recurrence_limit = n,
+                Err(_) => return false,
+            },
+            ("filling", "reverse") => self.filling_order = FillingOrder::Reverse,
+            ("filling", "forward") => self.filling_order = FillingOrder::Forward,
+            ("dead-anchors", "clear") => self.clear_dead_anchors = true,
+            ("dead-anchors", "keep") => self.clear_dead_anchors = false,
+            ("stuck", "ignore") => self.eliminate_stuck = false,
+            ("stuck", "eliminate") => self.eliminate_stuck = true,
+            _ => return false,
+        }
+        true
+    }
+}
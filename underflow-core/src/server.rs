@@ -1,13 +1,55 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
 use crate::{
-    Board, CellState,
+    Board, BoardShape, CellState, InitError,
     history::BoardHistory,
-    protocol::{FlowCommand, FlowError, FlowResponse, GamePhase},
+    protocol::{FlowCommand, FlowError, FlowEvent, FlowResponse, GamePhase},
+    rules::{FillingOrder, Rules},
+    splitmix64,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FlowServerConfig {
     pub player_count: u8,
-    pub size: u8,
+    pub shape: BoardShape,
+    pub rules: Rules,
+}
+
+impl FlowServerConfig {
+    /// Square board without holes
+    pub fn new(player_count: u8, size: u8) -> Self {
+        Self {
+            player_count,
+            shape: BoardShape::square(size),
+            rules: Rules::default(),
+        }
+    }
+
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+}
+
+/// Server state right before an accepted command
+#[derive(Clone)]
+struct Snapshot {
+    board: Board,
+    current_player: u8,
+    phase: GamePhase,
+    history_len: usize,
+    anchors: Vec<(u8, u8, u8)>,
+}
+
+/// Event channels of a server. A clone starts without any, so the copies a search plays on
+/// stay silent.
+#[derive(Default)]
+struct Subscribers(Vec<Sender<FlowEvent>>);
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
 }
 
 #[derive(Clone)]
@@ -16,18 +58,32 @@ pub struct FlowServer {
     pub current_player: u8,
     history: BoardHistory,
     pub phase: GamePhase,
-    player_count: u8,
+    config: FlowServerConfig,
+    /// `(player, x, y)` of the anchors on the board, oldest first
+    anchors: Vec<(u8, u8, u8)>,
+    /// Every accepted command, in order
+    commands: Vec<FlowCommand>,
+    /// One snapshot per accepted command
+    undo_stack: Vec<Snapshot>,
+    /// Undone commands, the last one is redone first
+    redo_stack: Vec<FlowCommand>,
+    subscribers: Subscribers,
 }
 
 impl FlowServer {
-    pub fn new(config: FlowServerConfig) -> Self {
-        Self {
-            board: Board::init(config.player_count, config.size),
+    pub fn new(config: FlowServerConfig) -> Result<Self, InitError> {
+        Ok(Self {
+            board: Board::init(config.player_count, &config.shape)?,
             current_player: 0,
-            history: BoardHistory::new(),
+            history: BoardHistory::with_mode(config.rules.flow),
             phase: GamePhase::Filling,
-            player_count: config.player_count,
-        }
+            config,
+            anchors: Vec::new(),
+            commands: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            subscribers: Subscribers::default(),
+        })
     }
 
     pub fn winning(&self) -> Option<u8> {
@@ -35,11 +91,11 @@ impl FlowServer {
             return None;
         }
         let mut winner = None;
-        for state in self.board.get_cells().iter().flat_map(|c| c.iter()) {
+        for state in self.board.cells() {
             if let CellState::Occupied(player) = state {
                 if winner.is_none() {
-                    winner = Some(*player);
-                } else if winner != Some(*player) {
+                    winner = Some(player);
+                } else if winner != Some(player) {
                     return None; // More than one player has occupied cells
                 }
             }
@@ -57,32 +113,86 @@ impl FlowServer {
     }
 
     pub fn can_flow_x(&self, y: u8) -> bool {
-        y < self.board.size && self.phase.is_flowing() && self.board.can_flow_x(y)
+        y < self.board.height && self.phase.is_flowing() && self.board.can_flow_x(y)
     }
 
     pub fn can_flow_y(&self, x: u8) -> bool {
-        x < self.board.size && self.phase.is_flowing() && self.board.can_flow_y(x)
+        x < self.board.width && self.phase.is_flowing() && self.board.can_flow_y(x)
     }
 
     pub fn player_count(&self) -> u8 {
-        self.player_count
+        self.config.player_count
+    }
+
+    pub fn config(&self) -> &FlowServerConfig {
+        &self.config
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.config.rules
+    }
+
+    /// A copy to look ahead with: same position, rules and recurrence history, but without the
+    /// command log, the undo and redo stacks or any subscriber. Cloning keeps the log, which
+    /// grows with the game.
+    pub fn fork(&self) -> Self {
+        Self {
+            board: self.board.clone(),
+            current_player: self.current_player,
+            history: self.history.clone(),
+            phase: self.phase,
+            config: self.config.clone(),
+            anchors: self.anchors.clone(),
+            commands: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    /// Hash of everything the moves from here depend on: the board, the player to move, the
+    /// recurrence history and the order of the anchors
+    pub fn state_hash(&self) -> u64 {
+        let anchors = self.anchors.iter().fold(0, |hash, &(player, x, y)| {
+            splitmix64(hash ^ ((player as u64) << 16 | (x as u64) << 8 | y as u64))
+        });
+        [self.history.zobrist(), anchors, self.current_player as u64]
+            .into_iter()
+            .fold(self.board.zobrist(), |hash, part| splitmix64(hash ^ part))
+    }
+
+    /// All the commands accepted by [FlowServer::handle] so far
+    pub fn commands(&self) -> &[FlowCommand] {
+        &self.commands
+    }
+
+    /// Receive the [FlowEvent]s of every command accepted from now on. Dropping the receiver
+    /// unsubscribes, clones of the server do not inherit subscriptions.
+    pub fn subscribe(&mut self) -> Receiver<FlowEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.0.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: FlowEvent) {
+        self.subscribers
+            .0
+            .retain(|subscriber| subscriber.send(event).is_ok());
     }
 
     fn player_alive(&self, player: u8) -> bool {
         self.phase.is_filling()
             || self
                 .board
-                .cells
-                .iter()
-                .flat_map(|c| c.iter())
-                .any(|&cell| cell == CellState::Occupied(player))
+                .cells()
+                .any(|cell| cell == CellState::Occupied(player))
     }
 
     fn current_player_alive(&self) -> bool {
         self.player_alive(self.current_player)
     }
 
-    fn check_player(&mut self, player: u8) -> FlowResponse {
+    fn check_player(&self, player: u8) -> FlowResponse {
         if !self.player_alive(player) {
             return Err(FlowError::YouAreDead);
         }
@@ -101,73 +211,209 @@ impl FlowServer {
         Ok(())
     }
 
-    fn checked_set(&mut self, x: u8, y: u8, state: CellState) -> FlowResponse {
-        let mut dry_run = self.board.clone();
-        dry_run.set(x, y, state);
-        if self.history.is_recurrence(&dry_run) {
-            return Err(FlowError::Recurrence);
-        }
-        self.board.set(x, y, state);
-        Ok(())
+    /// Whether the position has shown up more often than [Rules::recurrence_limit] allows
+    fn is_recurrence(&self, board: &Board) -> bool {
+        self.history.occurrences(board) > self.config.rules.recurrence_limit as usize
     }
 
-    fn checked_double_set(
-        &mut self,
-        pos1: (u8, u8),
-        state1: CellState,
-        pos2: (u8, u8),
-        state2: CellState,
-    ) -> FlowResponse {
-        let mut dry_run = self.board.clone();
-        dry_run.set(pos1.0, pos1.1, state1);
-        dry_run.set(pos2.0, pos2.1, state2);
-        if self.history.is_recurrence(&dry_run) {
-            return Err(FlowError::Recurrence);
+    /// The board after the player anchors at (x, y), their oldest anchor turns Neutral when they
+    /// are out of anchors
+    fn anchored_board(&self, player: u8, x: u8, y: u8) -> Board {
+        let mut board = self.board.clone();
+        let owned: Vec<_> = self.anchors.iter().filter(|a| a.0 == player).collect();
+        let limit = self.config.rules.anchors_per_player.max(1) as usize;
+        if owned.len() >= limit {
+            let (_, old_x, old_y) = *owned[0];
+            board.set(old_x, old_y, CellState::Neutral);
         }
-        self.board.set(pos1.0, pos1.1, state1);
-        self.board.set(pos2.0, pos2.1, state2);
-        Ok(())
+        board.set(x, y, CellState::Anchored(player));
+        board
     }
 
     pub fn will_be_recurrence(&self, idx: u8, is_x: bool, positive: bool) -> bool {
         let mut dry_run = self.board.clone();
-        if is_x {
-            if !dry_run.flow_x(idx, positive) {
-                return false;
-            }
-        } else if !dry_run.flow_y(idx, positive) {
+        if !dry_run.flow_line(is_x, idx, positive, self.config.rules.flow) {
             return false;
         }
-        self.history.is_recurrence(&dry_run)
+        self.is_recurrence(&dry_run)
     }
 
-    fn checked_flow(&mut self, idx: u8, is_x: bool, positive: bool) -> FlowResponse {
-        if idx >= self.board.size {
-            return Err(FlowError::IndexOutOfRange);
+    /// Check if the player can flow or anchor without a recurrence, whoever's turn it is
+    pub fn has_legal_move(&self, player: u8) -> bool {
+        self.flowing_candidates(player)
+            .into_iter()
+            .any(|cmd| self.board_after(cmd).is_ok())
+    }
+
+    /// Every command the player could try in the current phase: each Empty cell while filling,
+    /// each flow and each Neutral cell to anchor while flowing
+    pub fn candidate_moves(&self, player: u8) -> Vec<FlowCommand> {
+        match self.phase {
+            GamePhase::Filling => self
+                .board
+                .positions()
+                .filter(|&(x, y)| self.board.get(x, y) == CellState::Empty)
+                .map(|(x, y)| FlowCommand::SetOccupied { player, x, y })
+                .collect(),
+            GamePhase::Flowing => self.flowing_candidates(player),
         }
-        let mut dry_run = self.board.clone();
-        if is_x {
-            if !dry_run.flow_x(idx, positive) {
-                return Err(FlowError::BlockedByAnchor);
+    }
+
+    fn flowing_candidates(&self, player: u8) -> Vec<FlowCommand> {
+        let mut moves = Vec::new();
+        for y in 0..self.board.height {
+            for positive in [true, false] {
+                moves.push(FlowCommand::FlowX {
+                    player,
+                    y,
+                    positive,
+                });
+            }
+        }
+        for x in 0..self.board.width {
+            for positive in [true, false] {
+                moves.push(FlowCommand::FlowY {
+                    player,
+                    x,
+                    positive,
+                });
+            }
+        }
+        moves.extend(
+            self.board
+                .positions()
+                .filter(|&(x, y)| self.board.is_neutral(x, y))
+                .map(|(x, y)| FlowCommand::SetAnchor { player, x, y }),
+        );
+        moves
+    }
+
+    /// Every candidate move of the player with the reason it would be rejected, see
+    /// [FlowServer::candidate_moves]
+    pub fn move_checks(&self, player: u8) -> Vec<(FlowCommand, FlowResponse)> {
+        self.candidate_moves(player)
+            .into_iter()
+            .map(|cmd| (cmd, self.check(cmd)))
+            .collect()
+    }
+
+    /// The moves [FlowServer::handle] would accept from the player right now
+    pub fn legal_moves(&self, player: u8) -> Vec<FlowCommand> {
+        if self.check_player(player).is_err() {
+            return Vec::new();
+        }
+        self.candidate_moves(player)
+            .into_iter()
+            .filter(|&cmd| self.board_after(cmd).is_ok())
+            .collect()
+    }
+
+    /// Every legal move of the player with the position it leads to, played on a
+    /// [FlowServer::fork]. Each move is checked once, unlike handling it on a fork.
+    pub fn successors(&self, player: u8) -> Vec<(FlowCommand, FlowServer)> {
+        if self.check_player(player).is_err() {
+            return Vec::new();
+        }
+        self.candidate_moves(player)
+            .into_iter()
+            .filter_map(|cmd| {
+                let board = self.board_after(cmd).ok()?;
+                let mut next = self.fork();
+                next.commit(cmd, board);
+                Some((cmd, next))
+            })
+            .collect()
+    }
+
+    /// Whether [FlowServer::handle] would accept the command, without applying it
+    pub fn check(&self, cmd: FlowCommand) -> FlowResponse {
+        self.check_player(cmd.player())?;
+        self.board_after(cmd).map(|_| ())
+    }
+
+    /// The board right after the command, before dead anchors are cleared. Whose turn it is
+    /// is not checked.
+    fn board_after(&self, cmd: FlowCommand) -> Result<Board, FlowError> {
+        match cmd {
+            FlowCommand::FlowX { y, positive, .. } => self.flowed_board(y, true, positive),
+            FlowCommand::FlowY { x, positive, .. } => self.flowed_board(x, false, positive),
+            FlowCommand::SetAnchor { player, x, y } => {
+                self.expect_phase(GamePhase::Flowing)?;
+                if !self.board.contains(x, y) {
+                    return Err(FlowError::IndexOutOfRange);
+                }
+                if self.board.is_occupied(x, y) {
+                    return Err(FlowError::AlreadyOccupied);
+                }
+                let board = self.anchored_board(player, x, y);
+                if self.is_recurrence(&board) {
+                    return Err(FlowError::Recurrence);
+                }
+                Ok(board)
+            }
+            FlowCommand::SetOccupied { player, x, y } => {
+                self.expect_phase(GamePhase::Filling)?;
+                if !self.board.contains(x, y) {
+                    return Err(FlowError::IndexOutOfRange);
+                }
+                if self.board.is_occupied(x, y) || self.board.is_neutral(x, y) {
+                    return Err(FlowError::AlreadyOccupied);
+                }
+                let mut board = self.board.clone();
+                board.set(x, y, CellState::Occupied(player));
+                if self.is_recurrence(&board) {
+                    return Err(FlowError::Recurrence);
+                }
+                Ok(board)
+            }
+        }
+    }
+
+    /// Every cell of the player turns Neutral, including the anchor
+    fn eliminate(&mut self, player: u8) {
+        for (x, y) in self.board.positions() {
+            if let CellState::Occupied(id) | CellState::Anchored(id) = self.board.get(x, y) {
+                if id == player {
+                    self.board.set(x, y, CellState::Neutral);
+                }
+            }
+        }
+    }
+
+    fn eliminate_stuck_players(&mut self) {
+        while self.phase.is_flowing()
+            && self.winning().is_none()
+            && !self.has_legal_move(self.current_player)
+        {
+            self.eliminate(self.current_player);
+            self.next_player_alive();
+        }
+    }
+
+    fn flowed_board(&self, idx: u8, is_x: bool, positive: bool) -> Result<Board, FlowError> {
+        self.expect_phase(GamePhase::Flowing)?;
+        if idx
+            >= if is_x {
+                self.board.height
+            } else {
+                self.board.width
             }
-        } else if !dry_run.flow_y(idx, positive) {
+        {
+            return Err(FlowError::IndexOutOfRange);
+        }
+        let mut board = self.board.clone();
+        if !board.flow_line(is_x, idx, positive, self.config.rules.flow) {
             return Err(FlowError::BlockedByAnchor);
         }
-        if self.history.is_recurrence(&dry_run) {
+        if self.is_recurrence(&board) {
             return Err(FlowError::Recurrence);
         }
-        if is_x {
-            self.board.flow_x(idx, positive);
-        } else {
-            self.board.flow_y(idx, positive);
-        }
-        self.history.push(&self.board);
-        Ok(())
+        Ok(board)
     }
 
     fn next_player(&mut self) {
         self.current_player += 1;
-        self.current_player %= self.player_count;
+        self.current_player %= self.config.player_count;
     }
 
     pub fn next_player_alive(&mut self) {
@@ -178,66 +424,86 @@ impl FlowServer {
     }
 
     fn last_player(&mut self) {
-        self.current_player += self.player_count - 1;
-        self.current_player %= self.player_count;
+        self.current_player += self.config.player_count - 1;
+        self.current_player %= self.config.player_count;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Rewind to the state before the last accepted command, return the undone command
+    pub fn undo(&mut self) -> Option<FlowCommand> {
+        let snapshot = self.undo_stack.pop()?;
+        let cmd = self.commands.pop()?;
+        self.board = snapshot.board;
+        self.current_player = snapshot.current_player;
+        self.phase = snapshot.phase;
+        self.history.truncate(snapshot.history_len);
+        self.anchors = snapshot.anchors;
+        self.redo_stack.push(cmd);
+        self.emit(FlowEvent::MoveUndone(cmd));
+        Some(cmd)
+    }
+
+    /// Re-apply the last undone command, return the redone command. A command that no longer
+    /// applies stays on the redo stack.
+    pub fn redo(&mut self) -> Option<FlowCommand> {
+        let cmd = *self.redo_stack.last()?;
+        self.apply(cmd).ok()?;
+        self.redo_stack.pop();
+        Some(cmd)
     }
 
     pub fn handle(&mut self, cmd: FlowCommand) -> FlowResponse {
+        self.apply(cmd)?;
+        self.redo_stack.clear();
+        Ok(())
+    }
+
+    fn apply(&mut self, cmd: FlowCommand) -> FlowResponse {
+        self.check_player(cmd.player())?;
+        let board = self.board_after(cmd)?;
+        self.commit(cmd, board);
+        Ok(())
+    }
+
+    /// Play a checked command, `board` is what [FlowServer::board_after] gave for it
+    fn commit(&mut self, cmd: FlowCommand, board: Board) {
+        let snapshot = Snapshot {
+            board: self.board.clone(),
+            current_player: self.current_player,
+            phase: self.phase,
+            history_len: self.history.len(),
+            anchors: self.anchors.clone(),
+        };
+        // Only observers need to know who was alive before
+        let alive: Vec<_> = if self.subscribers.0.is_empty() {
+            Vec::new()
+        } else {
+            (0..self.config.player_count)
+                .filter(|&player| self.player_alive(player))
+                .collect()
+        };
+        self.board = board;
         match cmd {
-            FlowCommand::FlowX {
-                player,
-                y,
-                positive,
-            } => {
-                self.check_player(player)?;
-                self.expect_phase(GamePhase::Flowing)?;
-                self.checked_flow(y, true, positive)?;
-                self.next_player_alive();
-            }
-            FlowCommand::FlowY {
-                player,
-                x,
-                positive,
-            } => {
-                self.check_player(player)?;
-                self.expect_phase(GamePhase::Flowing)?;
-                self.checked_flow(x, false, positive)?;
+            FlowCommand::FlowX { .. } | FlowCommand::FlowY { .. } => {
+                self.history.push(&self.board);
                 self.next_player_alive();
             }
             FlowCommand::SetAnchor { player, x, y } => {
-                self.check_player(player)?;
-                self.expect_phase(GamePhase::Flowing)?;
-                if self.board.is_occupied(x, y) {
-                    return Err(FlowError::AlreadyOccupied);
-                }
-                let old = self
-                    .board
-                    .get_cells()
-                    .iter()
-                    .enumerate()
-                    .flat_map(|(x, col)| col.iter().enumerate().map(move |(y, cell)| (x, y, *cell)))
-                    .find(|(_, _, cell)| *cell == CellState::Anchored(player))
-                    .map(|(x, y, _)| (x as u8, y as u8));
-                if let Some(old) = old {
-                    self.checked_double_set(
-                        old,
-                        CellState::Neutral,
-                        (x, y),
-                        CellState::Anchored(player),
-                    )?;
-                } else {
-                    self.checked_set(x, y, CellState::Anchored(player))?;
-                }
+                self.anchors.push((player, x, y));
                 self.next_player_alive();
             }
-            FlowCommand::SetOccupied { player, x, y } => {
-                self.check_player(player)?;
-                self.expect_phase(GamePhase::Filling)?;
-                if self.board.is_occupied(x, y) || self.board.is_neutral(x, y) {
-                    return Err(FlowError::AlreadyOccupied);
+            FlowCommand::SetOccupied { .. } => {
+                match self.config.rules.filling_order {
+                    FillingOrder::Reverse => self.last_player(),
+                    FillingOrder::Forward => self.next_player(),
                 }
-                self.checked_set(x, y, CellState::Occupied(player))?;
-                self.last_player();
                 if self.board.is_ready() {
                     self.phase = GamePhase::Flowing;
                     self.history.push(&self.board);
@@ -245,27 +511,328 @@ impl FlowServer {
             }
         }
 
-        let dead_anchors: Vec<_> = self
+        let dead_anchors: Vec<_> = if !self.config.rules.clear_dead_anchors {
+            Vec::new()
+        } else {
+            self.board
+                .positions()
+                .filter(|&(x, y)| match self.board.get(x, y) {
+                    CellState::Anchored(player) => !self.player_alive(player),
+                    _ => false,
+                })
+                .collect()
+        };
+        for (x, y) in dead_anchors {
+            self.board.set(x, y, CellState::Neutral);
+        }
+        if self.config.rules.eliminate_stuck {
+            self.eliminate_stuck_players();
+        }
+        let board = &self.board;
+        let (kept, removed) = std::mem::take(&mut self.anchors)
+            .into_iter()
+            .partition(|&(player, x, y)| board.get(x, y) == CellState::Anchored(player));
+        self.anchors = kept;
+        let phase = snapshot.phase;
+        self.commands.push(cmd);
+        self.undo_stack.push(snapshot);
+
+        if !self.subscribers.0.is_empty() {
+            self.emit_events(cmd, phase, &alive, removed);
+        }
+    }
+
+    fn emit_events(
+        &mut self,
+        cmd: FlowCommand,
+        phase: GamePhase,
+        alive: &[u8],
+        removed: Vec<(u8, u8, u8)>,
+    ) {
+        self.emit(FlowEvent::MoveApplied(cmd));
+        if self.phase != phase {
+            self.emit(FlowEvent::PhaseChanged(self.phase));
+        }
+        for (player, x, y) in removed {
+            self.emit(FlowEvent::AnchorRemoved { player, x, y });
+        }
+        for &player in alive {
+            if !self.player_alive(player) {
+                self.emit(FlowEvent::PlayerEliminated(player));
+            }
+        }
+        if let Some(winner) = self.winning() {
+            self.emit(FlowEvent::GameWon(winner));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 2)).unwrap();
+        for (player, x, y) in [(0, 0, 0), (1, 1, 0), (0, 0, 1), (1, 1, 1)] {
+            server
+                .handle(FlowCommand::SetOccupied { player, x, y })
+                .unwrap();
+        }
+        let ready = server.board.clone();
+        let flow = FlowCommand::FlowX {
+            player: 0,
+            y: 0,
+            positive: true,
+        };
+        let hash = server.state_hash();
+        server.handle(flow).unwrap();
+        assert_eq!(server.current_player, 1);
+
+        assert_eq!(server.undo(), Some(flow));
+        assert_eq!(server.board, ready);
+        assert_eq!(server.current_player, 0);
+        assert_eq!(server.commands().len(), 4);
+        assert_eq!(server.state_hash(), hash);
+        // The same board with another recurrence history is another state
+        let mut other = server.fork();
+        other.history.push(&ready);
+        assert_ne!(other.state_hash(), hash);
+        // The flowed board left the recurrence history with the undo
+        server.handle(flow).unwrap();
+        server.undo();
+
+        // Undo across the phase change drops the initial board from the history too
+        server.undo();
+        assert_eq!(server.phase, GamePhase::Filling);
+        assert_eq!(server.current_player, 1);
+        assert!(server.history.is_empty());
+        assert!(server.can_redo());
+
+        assert!(server.redo().is_some());
+        assert_eq!(server.phase, GamePhase::Flowing);
+        // A redo rejected by the server keeps the command for later
+        server.current_player = 1;
+        assert_eq!(server.redo(), None);
+        assert!(server.can_redo());
+        server.current_player = 0;
+        assert_eq!(server.redo(), Some(flow));
+        assert_eq!(server.redo(), None);
+
+        server.undo();
+        server
+            .handle(FlowCommand::FlowY {
+                player: 0,
+                x: 0,
+                positive: false,
+            })
+            .unwrap();
+        assert!(!server.can_redo());
+
+        let fork = server.fork();
+        assert_eq!(fork.board, server.board);
+        assert!(fork.commands().is_empty());
+        assert!(!fork.can_undo());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let config = |rules| FlowServerConfig {
+            player_count: 2,
+            shape: BoardShape::rect(4, 1),
+            rules,
+        };
+        let fill = |server: &mut FlowServer| {
+            for (player, x) in [(0, 0), (1, 1), (0, 2), (1, 3)] {
+                server
+                    .handle(FlowCommand::SetOccupied { player, x, y: 0 })
+                    .unwrap();
+            }
+        };
+
+        let mut server = FlowServer::new(config(Rules::default())).unwrap();
+        fill(&mut server);
+        assert!(!server.will_be_recurrence(0, true, true));
+        assert_eq!(server.winning(), None);
+
+        // Rotating the only row is a translation of the whole torus, so player 0 is stuck
+        let mut server = FlowServer::new(config(Rules::wrap())).unwrap();
+        fill(&mut server);
+        assert_eq!(server.winning(), Some(1));
+        assert_eq!(server.board.get(0, 0), CellState::Neutral);
+    }
+
+    #[test]
+    fn test_events() {
+        let config = FlowServerConfig {
+            player_count: 2,
+            shape: BoardShape::rect(4, 1),
+            rules: Rules::wrap(),
+        };
+        let mut server = FlowServer::new(config).unwrap();
+        let events = server.subscribe();
+        let mut copy = server.clone();
+        for (player, x) in [(0, 0), (1, 1), (0, 2), (1, 3)] {
+            server
+                .handle(FlowCommand::SetOccupied { player, x, y: 0 })
+                .unwrap();
+        }
+        copy.handle(FlowCommand::SetOccupied {
+            player: 0,
+            x: 0,
+            y: 0,
+        })
+        .unwrap();
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(events.len(), 7);
+        assert_eq!(
+            events[4..],
+            [
+                FlowEvent::PhaseChanged(GamePhase::Flowing),
+                FlowEvent::PlayerEliminated(0),
+                FlowEvent::GameWon(1),
+            ]
+        );
+
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 3)).unwrap();
+        let mut board = Board::new(3, 3);
+        for (x, y) in board.positions() {
+            board.set(x, y, CellState::Neutral);
+        }
+        board.set(0, 0, CellState::Occupied(0));
+        board.set(2, 2, CellState::Occupied(1));
+        server.board = board;
+        server.phase = GamePhase::Flowing;
+        let events = server.subscribe();
+        for (player, x, y) in [(0, 1, 0), (1, 1, 2), (0, 0, 1)] {
+            server
+                .handle(FlowCommand::SetAnchor { player, x, y })
+                .unwrap();
+        }
+        let undone = server.undo().unwrap();
+        let events: Vec<_> = events.try_iter().skip(3).collect();
+        assert_eq!(
+            events,
+            [
+                FlowEvent::AnchorRemoved {
+                    player: 0,
+                    x: 1,
+                    y: 0
+                },
+                FlowEvent::MoveUndone(undone),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = Rules {
+            anchors_per_player: 2,
+            filling_order: FillingOrder::Forward,
+            ..Default::default()
+        };
+        let mut server = FlowServer::new(FlowServerConfig::new(3, 7).with_rules(rules)).unwrap();
+        let (x, y) = server
             .board
-            .get_cells()
-            .iter()
-            .enumerate()
-            .flat_map(|(x, col)| col.iter().enumerate().map(move |(y, cell)| (x, y, *cell)))
-            .filter_map(|(x, y, cell)| {
-                if let CellState::Anchored(player) = cell {
-                    if !self.player_alive(player) {
-                        Some((x as u8, y as u8))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
+            .positions()
+            .find(|&(x, y)| server.board.get(x, y) == CellState::Empty)
+            .unwrap();
+        server
+            .handle(FlowCommand::SetOccupied { player: 0, x, y })
+            .unwrap();
+        assert_eq!(server.current_player, 1);
+
+        let config = FlowServerConfig::new(2, 3).with_rules(rules);
+        let mut server = FlowServer::new(config).unwrap();
+        let mut board = Board::new(3, 3);
+        for (x, y) in board.positions() {
+            board.set(x, y, CellState::Neutral);
+        }
+        board.set(0, 0, CellState::Occupied(0));
+        board.set(2, 2, CellState::Occupied(1));
+        server.board = board;
+        server.phase = GamePhase::Flowing;
+        server.history.push(&server.board);
+
+        for (player, x, y) in [(0, 1, 0), (1, 1, 2), (0, 0, 1), (1, 2, 1)] {
+            server
+                .handle(FlowCommand::SetAnchor { player, x, y })
+                .unwrap();
+        }
+        assert_eq!(server.board.get(1, 0), CellState::Anchored(0));
+        assert_eq!(server.board.get(0, 1), CellState::Anchored(0));
+
+        // Out of anchors, the oldest one goes back to Neutral
+        server
+            .handle(FlowCommand::SetAnchor {
+                player: 0,
+                x: 1,
+                y: 1,
             })
+            .unwrap();
+        assert_eq!(server.board.get(1, 0), CellState::Neutral);
+        assert_eq!(server.board.get(0, 1), CellState::Anchored(0));
+
+        server.undo();
+        assert_eq!(server.board.get(1, 0), CellState::Anchored(0));
+        assert_eq!(server.anchors.len(), 4);
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        let mut server = FlowServer::new(FlowServerConfig::new(2, 3)).unwrap();
+        let legal = server.legal_moves(0);
+        assert_eq!(
+            legal.len(),
+            server
+                .board
+                .cells()
+                .filter(|&c| c == CellState::Empty)
+                .count()
+        );
+        assert!(server.legal_moves(1).is_empty());
+
+        let mut board = Board::new(3, 3);
+        for (x, y) in board.positions() {
+            board.set(x, y, CellState::Neutral);
+        }
+        board.set(0, 0, CellState::Occupied(0));
+        board.set(2, 2, CellState::Occupied(1));
+        board.set(1, 1, CellState::Anchored(1));
+        server.board = board;
+        server.phase = GamePhase::Flowing;
+        server.history.push(&server.board);
+
+        // The same moves as trying every candidate on a copy
+        let tried: Vec<_> = server
+            .candidate_moves(0)
+            .into_iter()
+            .filter(|&cmd| server.clone().handle(cmd).is_ok())
             .collect();
-        for (x, y) in dead_anchors {
-            self.board.set(x, y, CellState::Neutral);
+        assert_eq!(server.legal_moves(0), tried);
+        assert!(server.has_legal_move(0));
+        for (cmd, next) in server.successors(0) {
+            let mut handled = server.fork();
+            handled.handle(cmd).unwrap();
+            assert_eq!(next.state_hash(), handled.state_hash());
         }
-        Ok(())
+        assert!(server.successors(1).is_empty());
+
+        let checks = server.move_checks(0);
+        let reason = |cmd| checks.iter().find(|(c, _)| *c == cmd).unwrap().1;
+        let flow = |y| FlowCommand::FlowX {
+            player: 0,
+            y,
+            positive: true,
+        };
+        assert_eq!(reason(flow(1)), Err(FlowError::BlockedByAnchor));
+        assert_eq!(reason(flow(0)), Ok(()));
+        assert_eq!(server.check(flow(0)), Ok(()));
+        let flow = FlowCommand::FlowX {
+            player: 1,
+            y: 0,
+            positive: true,
+        };
+        assert_eq!(server.check(flow), Err(FlowError::NotYourTurn));
     }
 }
@@ -1,6 +1,9 @@
 start-game = START
 settings = SETTINGS
+replay-last-game = REPLAY LAST GAME
 quit = QUIT
+no-last-game = No finished game to replay yet
+last-game-unreadable = The last game could not be read
 
 back = BACK
 
@@ -8,13 +11,43 @@ player = PLAYER
 ai-easy = AI EASY
 ai-medium = AI MEDIUM
 ai-hard = AI HARD
+ai-mcts = AI MCTS
 plus = +
 ready = READY? START!
 
+board-size = Board
+size-auto = Auto
+turn-order = Turn order
+order-random = Random
+order-fixed = As listed
+flow-mode = Flow
+flow-push = Push out
+flow-wrap = Wrap around
+anchors = Anchors
+recurrence = Repeats
+filling-order = Filling
+filling-reverse = Reverse
+filling-forward = Forward
+dead-anchors = Dead anchors
+dead-anchors-clear = Clear
+dead-anchors-keep = Keep
+stuck-players = Stuck players
+stuck-ignore = Ignore
+stuck-eliminate = Eliminate
+save-preset = SAVE PRESET
+game-not-started = This setup cannot be played
+
 pause = PAUSE
 your-turn = YOUR TURN!
+waiting-players = WAITING FOR PLAYERS...
+thinking = THINKING...
+reviewing = REVIEWING
+back-to-live = BACK TO GAME
 
 you-win = YOU WIN!
+watch-replay = REPLAY
+play = PLAY
+speed = Speed
 
 resume = RESUME
 exit-to-menu = EXIT TO MENU
@@ -1,6 +1,9 @@
 start-game = 开始游戏
 settings = 设置
+replay-last-game = 回放上一局
 quit = 退出
+no-last-game = 还没有可以回放的对局
+last-game-unreadable = 无法读取上一局的记录
 
 back = 返回
 
@@ -8,13 +11,43 @@ player = 玩家
 ai-easy = 电脑（简单）
 ai-medium = 电脑（中等）
 ai-hard = 电脑（困难）
+ai-mcts = 电脑（蒙特卡洛）
 plus = +
 ready = 准备好了就开始吧！
 
+board-size = 棋盘大小
+size-auto = 自动
+turn-order = 行动顺序
+order-random = 随机
+order-fixed = 按列表
+flow-mode = 流动方式
+flow-push = 推出
+flow-wrap = 环绕
+anchors = 锚点数
+recurrence = 重复局面
+filling-order = 填充顺序
+filling-reverse = 倒序
+filling-forward = 正序
+dead-anchors = 失效锚点
+dead-anchors-clear = 清除
+dead-anchors-keep = 保留
+stuck-players = 无路可走
+stuck-ignore = 忽略
+stuck-eliminate = 淘汰
+save-preset = 保存预设
+game-not-started = 无法按这个设置开始游戏
+
 pause = 暂停
 your-turn = 轮到你了！
+waiting-players = 等待其他玩家加入…
+thinking = 思考中…
+reviewing = 回顾中
+back-to-live = 回到对局
 
 you-win = 您赢了！
+watch-replay = 观看回放
+play = 播放
+speed = 速度
 
 resume = 继续游戏
 exit-to-menu = 退出至菜单
@@ -78,7 +78,8 @@ pub struct LabeledButton {
     label_component: Label,
     raw_size: f32,
     pub inner: RoundedButton,
-    l10n_id: String,
+    /// None for a literal text
+    l10n_id: Option<String>,
 }
 
 impl LabeledButton {
@@ -114,12 +115,23 @@ impl LabeledButton {
         button_f: impl FnOnce(RoundedButton) -> RoundedButton,
     ) -> Self {
         let l10n_id = id.as_ref().to_string();
-        let label_component = label_f(Label::new(tl!(l10n_id.clone())));
+        let mut button = Self::new_with_text(tl!(l10n_id.clone()), label_f, button_f);
+        button.l10n_id = Some(l10n_id);
+        button
+    }
+
+    /// A button whose label is not localized, e.g. a name the player chose
+    pub fn new_with_text(
+        text: impl AsRef<str>,
+        label_f: impl FnOnce(Label) -> Label,
+        button_f: impl FnOnce(RoundedButton) -> RoundedButton,
+    ) -> Self {
+        let label_component = label_f(Label::new(text.as_ref()));
         Self {
             raw_size: label_component.font_size,
             label_component,
             inner: button_f(RoundedButton::default()),
-            l10n_id,
+            l10n_id: None,
         }
     }
 
@@ -135,7 +147,9 @@ impl LabeledButton {
 
 impl Layout for LabeledButton {
     fn before_render(&mut self, _: &Transform, _: &mut Window) {
-        self.label_component.text = tl!(self.l10n_id.clone()).into_owned();
+        if let Some(id) = &self.l10n_id {
+            self.label_component.text = tl!(id.clone()).into_owned();
+        }
         // ! TODO: memory issue here!!!
         // let size = 1.0
         //     - 0.04 * {
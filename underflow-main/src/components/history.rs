@@ -0,0 +1,160 @@
+use comui::{
+    component::Component,
+    components::label::Align,
+    layout::{Layout, LayoutBuilder},
+    utils::Transform,
+    window::Window,
+};
+use macroquad::color::Color;
+use underflow_core::protocol::FlowCommand;
+
+use crate::{colors, components::button::LabeledButton};
+
+/// Notation of a move for the history, e.g. `B: flow row 3 →`. Players are lettered by seat,
+/// rows and columns count from 1.
+pub fn notation(cmd: &FlowCommand) -> String {
+    let player = (b'A' + cmd.player()) as char;
+    match *cmd {
+        FlowCommand::SetOccupied { x, y, .. } => format!("{}: fill {} {}", player, x + 1, y + 1),
+        FlowCommand::SetAnchor { x, y, .. } => format!("{}: anchor {} {}", player, x + 1, y + 1),
+        FlowCommand::FlowX { y, positive, .. } => {
+            let arrow = if positive { '→' } else { '←' };
+            format!("{}: flow row {} {}", player, y + 1, arrow)
+        }
+        FlowCommand::FlowY { x, positive, .. } => {
+            let arrow = if positive { '↑' } else { '↓' };
+            format!("{}: flow col {} {}", player, x + 1, arrow)
+        }
+    }
+}
+
+/// Moves played so far, one clickable entry per move with the newest at the bottom
+pub struct HistoryPanel {
+    commands: Vec<FlowCommand>,
+    entries: Vec<LabeledButton>,
+    /// Index of the topmost entry in view
+    first: usize,
+    up_btn: LabeledButton,
+    down_btn: LabeledButton,
+    /// Highlighted entry
+    pub selected: Option<usize>,
+    clicked: Option<usize>,
+}
+
+impl HistoryPanel {
+    const ROWS: usize = 10;
+    const FONT_SIZE: f32 = 26.0;
+
+    fn button(text: &str, color: Color) -> LabeledButton {
+        LabeledButton::new_with_text(
+            text,
+            |l| {
+                l.with_align(Align::Center)
+                    .with_color(color)
+                    .with_font_size(Self::FONT_SIZE)
+                    .with_line_height(Self::FONT_SIZE)
+                    .with_texture_align((0.5, 0.6))
+            },
+            |b| {
+                b.with_color(colors::color_secondary_container())
+                    .with_radius(0.3)
+            },
+        )
+    }
+
+    pub fn new() -> Self {
+        Self {
+            commands: vec![],
+            entries: vec![],
+            first: 0,
+            up_btn: Self::button("↑", colors::color_secondary()),
+            down_btn: Self::button("↓", colors::color_secondary()),
+            selected: None,
+            clicked: None,
+        }
+    }
+
+    fn max_first(&self) -> usize {
+        self.entries.len().saturating_sub(Self::ROWS)
+    }
+
+    /// Follow the moves of the game, `colors` is indexed by player. The view sticks to the
+    /// newest move unless scrolled up.
+    pub fn sync(&mut self, commands: &[FlowCommand], colors: &[Color]) {
+        if self.commands == commands {
+            return;
+        }
+        let following = self.first == self.max_first();
+        let kept = self
+            .commands
+            .iter()
+            .zip(commands)
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.commands.truncate(kept);
+        self.entries.truncate(kept);
+        for cmd in &commands[kept..] {
+            let color = colors[cmd.player() as usize % colors.len()];
+            self.entries.push(Self::button(&notation(cmd), color));
+            self.commands.push(*cmd);
+        }
+        self.first = if following {
+            self.max_first()
+        } else {
+            self.first.min(self.max_first())
+        };
+        // The reviewed move was replaced or dropped
+        if self.selected.is_some_and(|idx| idx >= kept) {
+            self.selected = None;
+        }
+    }
+
+    /// Index of the entry clicked since the last call
+    pub fn clicked(&mut self) -> Option<usize> {
+        self.clicked.take()
+    }
+}
+
+impl Default for HistoryPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layout for HistoryPanel {
+    fn before_render(&mut self, _: &Transform, _: &mut Window) {
+        if self.up_btn.triggered() {
+            self.first = self.first.saturating_sub(Self::ROWS / 2);
+        }
+        if self.down_btn.triggered() {
+            self.first = (self.first + Self::ROWS / 2).min(self.max_first());
+        }
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            if entry.triggered() {
+                self.clicked = Some(idx);
+            }
+            entry.inner.color = if self.selected == Some(idx) {
+                colors::color_primary().with_alpha(0.3)
+            } else {
+                colors::color_secondary_container()
+            };
+        }
+    }
+
+    fn components(&mut self) -> Vec<(Transform, &mut dyn Component)> {
+        let row_height = 0.84 / Self::ROWS as f32;
+        let builder = LayoutBuilder::new()
+            .at_rect((0.0, 0.46, 1.0, 0.06), &mut self.up_btn)
+            .at_rect((0.0, -0.46, 1.0, 0.06), &mut self.down_btn);
+        self.entries
+            .iter_mut()
+            .skip(self.first)
+            .take(Self::ROWS)
+            .enumerate()
+            .fold(builder, |builder, (row, entry)| {
+                let y = 0.42 - (row as f32 + 0.5) * row_height;
+                builder.at_rect((0.0, y, 1.0, row_height * 0.9), entry)
+            })
+            .build()
+    }
+}
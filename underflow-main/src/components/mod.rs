@@ -1,5 +1,7 @@
 pub mod button;
 pub mod data_bar;
 pub mod empty;
+pub mod history;
 pub mod rounded_rect;
 pub mod single_choice;
+pub mod timeline;
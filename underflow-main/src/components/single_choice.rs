@@ -26,6 +26,10 @@ impl SingleChoice {
         }
     }
 
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
     pub fn updated(&mut self) -> bool {
         if self.btn.triggered {
             self.btn.triggered = false;
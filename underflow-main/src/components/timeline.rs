@@ -0,0 +1,97 @@
+use comui::{component::Component, shading::IntoShading, utils::Transform, window::Window};
+use lyon::{
+    geom::euclid::Point2D,
+    math::Box2D,
+    path::{Path, Winding},
+};
+use macroquad::{input::TouchPhase, prelude::Touch};
+
+use crate::{colors, utils::UTransform};
+
+/// Horizontal scrubber over the positions `0..=len`, dragging anywhere on it seeks
+pub struct Timeline {
+    pub len: usize,
+    pub position: usize,
+    /// Touch id of the drag in progress
+    dragging: Option<u64>,
+    /// Position picked by the user since the last call to [Timeline::seeked]
+    seeked: Option<usize>,
+}
+
+impl Timeline {
+    const TRACK_HEIGHT: f32 = 0.2;
+    const THUMB_WIDTH: f32 = 0.012;
+
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            position: 0,
+            dragging: None,
+            seeked: None,
+        }
+    }
+
+    pub fn seeked(&mut self) -> Option<usize> {
+        self.seeked.take()
+    }
+
+    fn position_x(&self) -> f32 {
+        if self.len == 0 {
+            return 0.5;
+        }
+        self.position as f32 / self.len as f32 - 0.5
+    }
+}
+
+impl Component for Timeline {
+    fn render(&mut self, tr: &Transform, target: &mut Window) {
+        let tr = UTransform::new(*tr);
+        let rect = |left: f32, right: f32, half_height: f32| {
+            let mut builder = Path::builder();
+            builder.add_rectangle(
+                &Box2D::new(
+                    Point2D::new(left, -half_height),
+                    Point2D::new(right, half_height),
+                ),
+                Winding::Positive,
+            );
+            builder.build().transformed(&tr)
+        };
+        let x = self.position_x();
+        let track = Self::TRACK_HEIGHT / 2.0;
+        target.fill_path(
+            &rect(-0.5, 0.5, track),
+            colors::color_secondary_container().into_shading(),
+            1.0,
+        );
+        target.fill_path(
+            &rect(-0.5, x, track),
+            colors::color_primary().with_alpha(0.6).into_shading(),
+            1.0,
+        );
+        target.fill_path(
+            &rect(x - Self::THUMB_WIDTH, x + Self::THUMB_WIDTH, 0.5),
+            colors::color_primary().into_shading(),
+            1.0,
+        );
+    }
+
+    fn touch(&mut self, touch: &Touch) -> anyhow::Result<bool> {
+        let (x, y) = (touch.position.x, touch.position.y);
+        let inside = (-0.5..=0.5).contains(&x) && (-0.5..=0.5).contains(&y);
+        match touch.phase {
+            TouchPhase::Started if inside => self.dragging = Some(touch.id),
+            TouchPhase::Ended | TouchPhase::Cancelled if self.dragging == Some(touch.id) => {
+                self.dragging = None;
+            }
+            _ if self.dragging == Some(touch.id) => {}
+            _ => return Ok(false),
+        }
+        let position = ((x + 0.5).clamp(0.0, 1.0) * self.len as f32).round() as usize;
+        if position != self.position {
+            self.position = position;
+            self.seeked = Some(position);
+        }
+        Ok(true)
+    }
+}
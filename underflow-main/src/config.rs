@@ -1,28 +1,327 @@
-use underflow_l10n::{FALLBACK_LANG, GLOBAL, LANGS, set_prefered_locale};
+//! Settings kept between runs, as `key value` lines in the platform config directory, next to
+//! the record of the last finished game
+//!
+//! ```text
+//! underflow-config 1
+//! language zh-CN
+//! new-player human
+//! ai-think 1500
+//! players human,hard
+//! board-size auto
+//! shuffle on
+//!
+//! preset human/hard 5 flow=wrap
+//! players human,hard
+//! board-size 5
+//! shuffle off
+//! rule flow wrap
+//! ```
+//!
+//! The game setup keys `players`, `board-size`, `shuffle` and `rule <key> <value>` (see
+//! [Rules::set]) describe the last game played, or the preset named by the `preset` line above
+//! them. Missing keys keep their default and unknown ones are skipped, so a file written by an
+//! older or newer version still loads, and the file is written back in the current format on
+//! the next save.
 
-pub static mut CONFIG: Option<Config> = None;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard};
+use std::time::Duration;
+
+use macroquad::prelude::info;
+use once_cell::sync::Lazy;
+use underflow_ai::Difficulty;
+use underflow_core::{record::GameRecord, rules::Rules};
+use underflow_l10n::set_prefered_locale;
+
+const HEADER: &str = "underflow-config";
+const VERSION: u32 = 1;
+
+static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| RwLock::new(Config::load()));
+
+/// Who sits in a seat of a local game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seat {
+    Human,
+    AI(Difficulty),
+}
+
+impl Display for Seat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Seat::Human => write!(f, "human"),
+            Seat::AI(Difficulty::Easy) => write!(f, "easy"),
+            Seat::AI(Difficulty::Medium) => write!(f, "medium"),
+            Seat::AI(Difficulty::Hard) => write!(f, "hard"),
+            Seat::AI(Difficulty::Mcts) => write!(f, "mcts"),
+        }
+    }
+}
+
+impl FromStr for Seat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "human" => Seat::Human,
+            "easy" => Seat::AI(Difficulty::Easy),
+            "medium" => Seat::AI(Difficulty::Medium),
+            "hard" => Seat::AI(Difficulty::Hard),
+            "mcts" => Seat::AI(Difficulty::Mcts),
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Everything the preflight screen sets up for a local game
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSetup {
+    pub players: Vec<Seat>,
+    /// None picks [underflow_core::server::FlowServer::optimal_size] for the player count
+    pub board_size: Option<u8>,
+    /// Randomize the turn order, otherwise players move in the order they are listed
+    pub shuffle: bool,
+    pub rules: Rules,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        Self {
+            players: vec![Seat::Human, Seat::Human],
+            board_size: None,
+            shuffle: true,
+            rules: Rules::default(),
+        }
+    }
+}
+
+impl GameSetup {
+    /// Every player of a local game needs a color of their own
+    pub const MIN_PLAYERS: usize = 2;
+    pub const MAX_PLAYERS: usize = 4;
+
+    /// Short description used to name presets, e.g. `human/hard 5 flow=wrap`
+    pub fn summary(&self) -> String {
+        let players: Vec<_> = self.players.iter().map(Seat::to_string).collect();
+        let mut summary = players.join("/");
+        if let Some(size) = self.board_size {
+            summary += &format!(" {}", size);
+        }
+        for (key, value) in self.rules.changes() {
+            summary += &format!(" {}={}", key, value);
+        }
+        summary
+    }
+
+    fn write(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let players: Vec<_> = self.players.iter().map(Seat::to_string).collect();
+        writeln!(f, "players {}", players.join(","))?;
+        match self.board_size {
+            Some(size) => writeln!(f, "board-size {}", size)?,
+            None => writeln!(f, "board-size auto")?,
+        }
+        writeln!(f, "shuffle {}", if self.shuffle { "on" } else { "off" })?;
+        for (key, value) in self.rules.changes() {
+            writeln!(f, "rule {} {}", key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Apply one setup key, return `false` if the key or the value is unknown
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match (key, value) {
+            ("players", value) => value
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|players| (Self::MIN_PLAYERS..=Self::MAX_PLAYERS).contains(&players.len()))
+                .map(|players| self.players = players)
+                .is_some(),
+            ("board-size", "auto") => {
+                self.board_size = None;
+                true
+            }
+            ("board-size", value) => value
+                .parse()
+                .map(|size| self.board_size = Some(size))
+                .is_ok(),
+            ("shuffle", "on") => {
+                self.shuffle = true;
+                true
+            }
+            ("shuffle", "off") => {
+                self.shuffle = false;
+                true
+            }
+            ("rule", value) => value
+                .split_once(' ')
+                .is_some_and(|(key, value)| self.rules.set(key, value.trim())),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub setup: GameSetup,
+}
 
 #[derive(Debug, Clone)]
-pub(crate) struct Config {
+pub struct Config {
+    /// None follows the system language
     pub language: Option<String>,
+    /// Seat added with the plus button
+    pub new_player: Seat,
+    /// Time the AI may search per move
+    pub ai_think: Duration,
+    /// The preflight screen starts with the last setup that was played
+    pub setup: GameSetup,
+    pub presets: Vec<Preset>,
 }
 
-pub fn set_config(data: Config) {
-    unsafe {
-        CONFIG = Some(data);
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            language: None,
+            new_player: Seat::Human,
+            ai_think: Duration::from_millis(1500),
+            setup: GameSetup::default(),
+            presets: Vec::new(),
+        }
+    }
+}
+
+impl Display for Config {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        if let Some(language) = &self.language {
+            writeln!(f, "language {}", language)?;
+        }
+        writeln!(f, "new-player {}", self.new_player)?;
+        writeln!(f, "ai-think {}", self.ai_think.as_millis())?;
+        self.setup.write(f)?;
+        for preset in &self.presets {
+            writeln!(f)?;
+            writeln!(f, "preset {}", preset.name)?;
+            preset.setup.write(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Platform config directory of the game
+fn dir() -> Option<PathBuf> {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    let dir = if cfg!(target_os = "windows") {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    Some(dir?.join("underflow"))
+}
+
+impl Config {
+    pub const MAX_PRESETS: usize = 4;
+
+    /// Keep the setup as a preset named after it, an older preset of the same name is replaced
+    /// and the oldest one is dropped once there are more than [Config::MAX_PRESETS]
+    pub fn add_preset(&mut self, setup: GameSetup) {
+        let name = setup.summary();
+        self.presets.retain(|preset| preset.name != name);
+        self.presets.push(Preset { name, setup });
+        if self.presets.len() > Self::MAX_PRESETS {
+            self.presets.remove(0);
+        }
+    }
+
+    /// Read a config file, anything that does not parse keeps its default
+    fn parse(s: &str) -> Self {
+        let mut config = Config::default();
+        let mut lines = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .peekable();
+        // Any version reads the same keys, a missing header is no different
+        lines.next_if(|line| line.starts_with(HEADER));
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            let ok = match key {
+                "language" => {
+                    config.language = Some(value.to_string());
+                    true
+                }
+                "new-player" => value.parse().map(|seat| config.new_player = seat).is_ok(),
+                "ai-think" => value
+                    .parse()
+                    .map(|ms| config.ai_think = Duration::from_millis(ms))
+                    .is_ok(),
+                "preset" if !value.is_empty() => {
+                    config.presets.push(Preset {
+                        name: value.to_string(),
+                        setup: GameSetup::default(),
+                    });
+                    true
+                }
+                key => match config.presets.last_mut() {
+                    Some(preset) => preset.setup.set(key, value),
+                    None => config.setup.set(key, value),
+                },
+            };
+            if !ok {
+                info!("Skipping config line `{}`", line);
+            }
+        }
+        config
+    }
+
+    fn path() -> Option<PathBuf> {
+        Some(dir()?.join("config.txt"))
+    }
+
+    fn load() -> Self {
+        match Self::path().map(std::fs::read_to_string) {
+            Some(Ok(text)) => Self::parse(&text),
+            _ => Self::default(),
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+        let res = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, self.to_string()));
+        if let Err(e) = res {
+            info!("Cannot save config to {}: {}", path.display(), e);
+        }
     }
 }
 
-#[allow(static_mut_refs)]
-pub fn get_config() -> &'static Config {
-    unsafe { CONFIG.as_ref().unwrap() }
+pub fn get_config() -> RwLockReadGuard<'static, Config> {
+    CONFIG.read().unwrap()
 }
 
-#[allow(static_mut_refs)]
-pub fn get_config_mut() -> &'static mut Config {
-    unsafe { CONFIG.as_mut().unwrap() }
+/// Change the config and save it
+pub fn update_config(f: impl FnOnce(&mut Config)) {
+    let mut config = CONFIG.write().unwrap();
+    f(&mut config);
+    config.save();
 }
 
+/// Apply the language choice
 pub fn sync_config() {
     set_prefered_locale(
         get_config()
@@ -30,17 +329,128 @@ pub fn sync_config() {
             .as_ref()
             .and_then(|it| it.parse().ok()),
     );
-    if get_config().language.is_none() {
-        get_config_mut().language = Some(LANGS[GLOBAL.order.lock().unwrap()[0]].to_owned());
+}
+
+fn last_game_path() -> Option<PathBuf> {
+    Some(dir()?.join("last-game.txt"))
+}
+
+/// Keep the record of a finished game for the replay entry of the start menu
+pub fn save_last_game(record: &GameRecord) {
+    let Some(path) = last_game_path() else {
+        return;
+    };
+    let res = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, record.to_string()));
+    if let Err(e) = res {
+        info!("Cannot save the game to {}: {}", path.display(), e);
     }
 }
 
-// TODO: save config
+pub fn load_last_game() -> Option<GameRecord> {
+    let text = std::fs::read_to_string(last_game_path()?).ok()?;
+    text.parse()
+        .inspect_err(|e| info!("Cannot read the last game: {}", e))
+        .ok()
+}
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            language: Some(FALLBACK_LANG.to_string()),
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let mut config = Config {
+            language: Some("zh-CN".to_string()),
+            new_player: Seat::AI(Difficulty::Hard),
+            ai_think: Duration::from_millis(800),
+            setup: GameSetup {
+                players: vec![Seat::Human, Seat::AI(Difficulty::Mcts), Seat::Human],
+                board_size: Some(7),
+                shuffle: false,
+                rules: Rules::wrap(),
+            },
+            presets: Vec::new(),
+        };
+        config.add_preset(config.setup.clone());
+        config.add_preset(GameSetup::default());
+
+        let text = config.to_string();
+        assert!(text.starts_with(&format!("{} {}\n", HEADER, VERSION)));
+        let parsed = Config::parse(&text);
+        assert_eq!(parsed.language, config.language);
+        assert_eq!(parsed.new_player, config.new_player);
+        assert_eq!(parsed.ai_think, config.ai_think);
+        assert_eq!(parsed.setup, config.setup);
+        assert_eq!(parsed.presets, config.presets);
+        assert_eq!(parsed.to_string(), text);
+    }
+
+    #[test]
+    fn test_config_header() {
+        let setup = GameSetup {
+            players: vec![Seat::Human, Seat::AI(Difficulty::Easy)],
+            ..Default::default()
+        };
+        for text in [
+            "underflow-config 0\nplayers human,easy",
+            "players human,easy",
+        ] {
+            let config = Config::parse(text);
+            assert_eq!(config.setup, setup);
+            assert!(
+                config
+                    .to_string()
+                    .starts_with(&format!("{} {}\n", HEADER, VERSION))
+            );
         }
     }
+
+    #[test]
+    fn test_config_skipped_lines() {
+        let config = Config::parse(
+            "underflow-config 1\n\
+             ai-think soon\n\
+             color blue\n\
+             players human,medium,hard\n\
+             rule anchors many\n\
+             preset solo\n\
+             players human\n\
+             shuffle off\n\
+             preset crowd\n\
+             players human,easy,easy,easy,easy\n\
+             board-size 9",
+        );
+        let defaults = Config::default();
+        assert_eq!(config.language, defaults.language);
+        assert_eq!(config.ai_think, defaults.ai_think);
+        assert_eq!(
+            config.setup,
+            GameSetup {
+                players: vec![
+                    Seat::Human,
+                    Seat::AI(Difficulty::Medium),
+                    Seat::AI(Difficulty::Hard)
+                ],
+                ..Default::default()
+            }
+        );
+        // Player counts the game cannot seat keep the default players
+        let presets: Vec<_> = config.presets.iter().map(|p| &p.setup).collect();
+        assert_eq!(
+            presets,
+            [
+                &GameSetup {
+                    shuffle: false,
+                    ..Default::default()
+                },
+                &GameSetup {
+                    board_size: Some(9),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
 }
@@ -3,7 +3,7 @@ underflow_l10n::tl_file!("common" tl crate::);
 use comui::{
     component::Component,
     layout::{Layout, LayoutBuilder},
-    scene::SceneManager,
+    scene::{Scene, SceneManager},
     utils::Transform,
     window::Window,
 };
@@ -13,8 +13,14 @@ use macroquad::{
     window::{clear_background, next_frame, screen_height, screen_width},
 };
 use nalgebra::Matrix3;
+use underflow_core::{net::DEFAULT_PORT, record::GameRecord};
+use underflow_server::Client;
 
-use crate::{config::set_config, input::InputHandler, scenes::startup::StartupScene};
+use crate::{
+    config::sync_config,
+    input::InputHandler,
+    scenes::{game::GameScene, replay::ReplayScene, startup::StartupScene},
+};
 
 mod colors;
 mod components;
@@ -38,31 +44,71 @@ struct Main {
     scene_manager: SceneManager,
 }
 
-impl Default for Main {
-    fn default() -> Self {
+impl Main {
+    fn new(scene_stack: Vec<Box<dyn Scene>>) -> Self {
         Self {
-            scene_manager: SceneManager {
-                scene_stack: vec![Box::new(StartupScene::default()) as Box<dyn comui::scene::Scene>],
-            },
+            scene_manager: SceneManager { scene_stack },
         }
     }
 }
 
-impl Layout for Main {
-    fn components(&mut self) -> Vec<(Transform, &mut dyn Component)> {
-        let (w, h) = (screen_width(), screen_height());
-        LayoutBuilder::new()
-            .at_rect((w / 2.0, h / 2.0, w, -h), &mut self.scene_manager)
-            .build()
+/// `--join <host>[:<port>] [--seat <n>]` starts in a networked game hosted by `underflow-server`,
+/// `--watch <host>[:<port>]` follows one without a seat, `--replay <path>` opens a game record.
+/// Leaving it returns to the start menu.
+fn first_scenes() -> Vec<Box<dyn Scene>> {
+    let mut scenes: Vec<Box<dyn Scene>> = vec![Box::new(StartupScene::default())];
+    let mut join = None;
+    let mut watch = false;
+    let mut seat = None;
+    let mut replay = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--join" => join = args.next(),
+            "--watch" => {
+                join = args.next();
+                watch = true;
+            }
+            "--seat" => seat = args.next().and_then(|s| s.parse().ok()),
+            "--replay" => replay = args.next(),
+            _ => info!("Unknown argument `{}`", arg),
+        }
+    }
+    if let Some(path) = replay {
+        let load = || -> anyhow::Result<ReplayScene> {
+            let record: GameRecord = std::fs::read_to_string(&path)?.parse()?;
+            Ok(ReplayScene::with_seat_colors(record)?)
+        };
+        match load() {
+            Ok(scene) => scenes.push(Box::new(scene)),
+            Err(e) => info!("Cannot replay {}: {}", path, e),
+        }
+        return scenes;
+    }
+    let Some(mut addr) = join else {
+        return scenes;
+    };
+    if !addr.contains(':') {
+        addr = format!("{}:{}", addr, DEFAULT_PORT);
+    }
+    let client = if watch {
+        Client::watch(addr.as_str())
+    } else {
+        Client::join(addr.as_str(), seat)
+    };
+    match client {
+        Ok((client, server)) => scenes.push(Box::new(GameScene::join(client, server))),
+        Err(e) => info!("Cannot join {}: {}", addr, e),
     }
+    scenes
 }
 
 #[macroquad::main(macroquad_config)]
 async fn main() {
-    set_config(Default::default());
+    sync_config();
 
     let mut handler = InputHandler::default();
-    let mut main_view = Main::default();
+    let mut main_view = Main::new(first_scenes());
     let mut window = Window::default();
     loop {
         handler.update();
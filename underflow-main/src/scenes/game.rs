@@ -1,4 +1,4 @@
-use std::iter::once;
+use std::{iter::once, thread};
 
 use comui::{
     component::Component,
@@ -17,16 +17,21 @@ use lyon::{
 use macroquad::{input::TouchPhase, prelude::Touch};
 use nalgebra::Vector2;
 use rand::seq::SliceRandom;
-use underflow_ai::AI;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use underflow_ai::{AI, Difficulty, OperationError, SearchBudget};
 use underflow_core::{
-    CellState,
+    CellState, InitError,
+    net::{ClientMessage, ServerMessage},
     protocol::{FlowCommand, GamePhase},
+    record::GameRecord,
     server::{FlowServer, FlowServerConfig},
 };
+use underflow_server::Client;
 
 use crate::{
     colors,
-    components::button::LabeledButton,
+    components::{button::LabeledButton, history::HistoryPanel},
+    config::{GameSetup, get_config, save_last_game},
     scenes::{pause::PauseScene, preflight::Player, win::WinScene},
     tl,
     utils::UTransform,
@@ -39,6 +44,10 @@ pub struct BoardComponent {
     pub flow_btns: [Vec<bool>; 4],
 
     pub color_map: Vec<Player>,
+    /// Ignore touches, for positions that are only shown
+    read_only: bool,
+    /// State hash and interactivity of the last shown position
+    shown: Option<(u64, bool)>,
 
     touch_scaling: (f32, f32),
     /// Tracking touch id and grid coord
@@ -50,22 +59,99 @@ impl BoardComponent {
     const CELL_GAP: f32 = 0.1;
     const BTN_GAP_RATIO: f32 = 5.5;
 
-    fn board_length(&self) -> usize {
+    pub fn new(color_map: Vec<Player>, server: &FlowServer) -> Self {
+        let mut board = Self {
+            cells: vec![],
+            flow_btns: Default::default(),
+            color_map,
+            read_only: true,
+            shown: None,
+
+            touch_scaling: (1.0, 1.0),
+            touch_state: None,
+            triggered_grid: None,
+        };
+        board.show(server, false);
+        board
+    }
+
+    /// Draw the board of the server. An interactive board takes touches and has the flow
+    /// buttons of the legal moves of the player to move.
+    pub fn show(&mut self, server: &FlowServer, interactive: bool) {
+        self.read_only = !interactive;
+        let shown = (server.state_hash(), interactive);
+        if self.shown == Some(shown) {
+            return;
+        }
+        self.shown = Some(shown);
+        let (width, height) = (server.board.width(), server.board.height());
+        self.cells.resize_with(width as usize, Vec::new);
+        for (x, col) in self.cells.iter_mut().enumerate() {
+            col.clear();
+            col.extend((0..height).map(|y| server.board.get(x as u8, y)));
+        }
+        let player = server.current_player;
+        let legal = if interactive {
+            server.legal_moves(player)
+        } else {
+            vec![]
+        };
+        let flow_x = |y, positive| {
+            legal.contains(&FlowCommand::FlowX {
+                player,
+                y,
+                positive,
+            })
+        };
+        let flow_y = |x, positive| {
+            legal.contains(&FlowCommand::FlowY {
+                player,
+                x,
+                positive,
+            })
+        };
+        self.flow_btns = [
+            (0..width).map(|x| flow_y(x, false)).collect(),
+            (0..height).map(|y| flow_x(y, true)).collect(),
+            (0..height).map(|y| flow_x(y, false)).collect(),
+            (0..width).map(|x| flow_y(x, true)).collect(),
+        ];
+    }
+
+    fn board_width(&self) -> usize {
         self.cells.len()
     }
 
+    fn board_height(&self) -> usize {
+        self.cells.first().map_or(0, |col| col.len())
+    }
+
+    /// Side length of a grid slot, the grid has a ring of flow buttons around the cells
+    fn unit(&self) -> f32 {
+        1.0 / (self.board_width().max(self.board_height()) + 2) as f32
+    }
+
+    /// Center of the grid slot, slot (1, 1) is the cell (0, 0)
+    fn slot_center(&self, x: f32, y: f32) -> (f32, f32) {
+        let unit = self.unit();
+        (
+            (x + 0.5 - (self.board_width() + 2) as f32 / 2.0) * unit,
+            (y + 0.5 - (self.board_height() + 2) as f32 / 2.0) * unit,
+        )
+    }
+
     fn grid_coord_to_elem(&self, x: usize, y: usize) -> GridElem {
-        let l = self.board_length();
+        let (w, h) = (self.board_width(), self.board_height());
         if x == 0 {
             return GridElem::LeftBtn { y: y as u8 - 1 };
         }
-        if x == l + 1 {
+        if x == w + 1 {
             return GridElem::RightBtn { y: y as u8 - 1 };
         }
         if y == 0 {
             return GridElem::BottomBtn { x: x as u8 - 1 };
         }
-        if y == l + 1 {
+        if y == h + 1 {
             return GridElem::TopBtn { x: x as u8 - 1 };
         }
         GridElem::Cell {
@@ -146,11 +232,15 @@ impl Component for BoardComponent {
 
         // Draw flow btns
         let mut btn_builder = Path::builder();
-        let btn_size =
-            1.0 / (1.0 + Self::CELL_GAP * Self::BTN_GAP_RATIO) / (self.board_length() + 2) as f32;
+        let unit = self.unit();
+        let (half_w, half_h) = (
+            (self.board_width() + 2) as f32 / 2.0 * unit,
+            (self.board_height() + 2) as f32 / 2.0 * unit,
+        );
+        let btn_size = unit / (1.0 + Self::CELL_GAP * Self::BTN_GAP_RATIO);
         for (x, _) in self.flow_btns[0].iter().enumerate().filter(|(_, d)| **d) {
-            let center_x = (x as f32 + 1.5) / (self.board_length() + 2) as f32 - 0.5;
-            let base_y = 0.5 - btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
+            let center_x = self.slot_center(x as f32 + 1.0, 0.0).0;
+            let base_y = half_h - btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
             btn_builder.add_polygon(Polygon {
                 closed: true,
                 points: &[
@@ -161,8 +251,8 @@ impl Component for BoardComponent {
             });
         }
         for (x, _) in self.flow_btns[3].iter().enumerate().filter(|(_, d)| **d) {
-            let center_x = (x as f32 + 1.5) / (self.board_length() + 2) as f32 - 0.5;
-            let base_y = -0.5 + btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
+            let center_x = self.slot_center(x as f32 + 1.0, 0.0).0;
+            let base_y = -half_h + btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
             btn_builder.add_polygon(Polygon {
                 closed: true,
                 points: &[
@@ -173,8 +263,8 @@ impl Component for BoardComponent {
             });
         }
         for (y, _) in self.flow_btns[1].iter().enumerate().filter(|(_, d)| **d) {
-            let center_y = (y as f32 + 1.5) / (self.board_length() + 2) as f32 - 0.5;
-            let base_x = -0.5 + btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
+            let center_y = self.slot_center(0.0, y as f32 + 1.0).1;
+            let base_x = -half_w + btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
             btn_builder.add_polygon(Polygon {
                 closed: true,
                 points: &[
@@ -185,8 +275,8 @@ impl Component for BoardComponent {
             });
         }
         for (y, _) in self.flow_btns[2].iter().enumerate().filter(|(_, d)| **d) {
-            let center_y = (y as f32 + 1.5) / (self.board_length() + 2) as f32 - 0.5;
-            let base_x = 0.5 - btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
+            let center_y = self.slot_center(0.0, y as f32 + 1.0).1;
+            let base_x = half_w - btn_size / 2.0 * Self::CELL_GAP * Self::BTN_GAP_RATIO;
             btn_builder.add_polygon(Polygon {
                 closed: true,
                 points: &[
@@ -212,9 +302,9 @@ impl Component for BoardComponent {
             .collect();
         let mut empty_builder = Path::builder();
         let mut neutral_builder = Path::builder();
-        let board_size = self.board_length();
-        (0..(board_size.pow(2)))
-            .map(|i| (i % board_size, i / board_size))
+        let cell_size = unit / (1.0 + Self::CELL_GAP);
+        (0..self.board_width())
+            .flat_map(|x| (0..self.board_height()).map(move |y| (x, y)))
             .for_each(|(x, y)| {
                 let builder = match self.cells[x][y] {
                     CellState::Occupied(id) => &mut builders[id as usize].1,
@@ -224,10 +314,9 @@ impl Component for BoardComponent {
                         anchors.push((x, y));
                         &mut builders[id as usize].1
                     }
+                    CellState::Blocked => return,
                 };
-                let cell_size = 1.0 / (1.0 + Self::CELL_GAP) / (board_size + 2) as f32;
-                let center_x = (x as f32 + 1.5) / (board_size + 2) as f32 - 0.5;
-                let center_y = (y as f32 + 1.5) / (board_size + 2) as f32 - 0.5;
+                let (center_x, center_y) = self.slot_center(x as f32 + 1.0, y as f32 + 1.0);
                 builder.add_rectangle(
                     &Box2D::new(
                         Point2D::new(center_x - cell_size * 0.5, center_y - cell_size * 0.5),
@@ -252,9 +341,7 @@ impl Component for BoardComponent {
         // Draw anchors
         let mut builder = Path::builder();
         for (x, y) in anchors {
-            let cell_size = 1.0 / (1.0 + Self::CELL_GAP) / (board_size + 2) as f32;
-            let center_x = (x as f32 + 1.5) / (board_size + 2) as f32 - 0.5;
-            let center_y = (y as f32 + 1.5) / (board_size + 2) as f32 - 0.5;
+            let (center_x, center_y) = self.slot_center(x as f32 + 1.0, y as f32 + 1.0);
             builder.add_circle(
                 point(center_x, center_y),
                 cell_size * 0.4,
@@ -270,26 +357,24 @@ impl Component for BoardComponent {
     }
 
     fn touch(&mut self, touch: &Touch) -> anyhow::Result<bool> {
-        if self.triggered_grid.is_some() {
+        if self.read_only || self.triggered_grid.is_some() {
             return Ok(false);
         }
         let (x, y) = (
             (touch.position.x * self.touch_scaling.0),
             (touch.position.y * self.touch_scaling.1),
         );
-        if !((-0.5..=0.5).contains(&x) && (-0.5..=0.5).contains(&y)) {
-            return Ok(false);
-        }
-        let l = self.board_length();
+        let (w, h) = (self.board_width(), self.board_height());
+        let unit = self.unit();
         let (grid_x, grid_y) = (
-            ((x + 0.5) * (l + 2) as f32).floor() as usize,
-            ((y + 0.5) * (l + 2) as f32).floor() as usize,
+            (x / unit + (w + 2) as f32 / 2.0).floor(),
+            (y / unit + (h + 2) as f32 / 2.0).floor(),
         );
-        if (grid_x, grid_y) == (0, 0)
-            || (grid_x, grid_y) == (0, l + 1)
-            || (grid_x, grid_y) == (l + 1, 0)
-            || (grid_x, grid_y) == (l + 1, l + 1)
-        {
+        if !((0.0..(w + 2) as f32).contains(&grid_x) && (0.0..(h + 2) as f32).contains(&grid_y)) {
+            return Ok(false);
+        }
+        let (grid_x, grid_y) = (grid_x as usize, grid_y as usize);
+        if (grid_x == 0 || grid_x == w + 1) && (grid_y == 0 || grid_y == h + 1) {
             return Ok(false);
         }
         let should_consume = match touch.phase {
@@ -325,6 +410,16 @@ pub struct GameScene {
     pub board: BoardComponent,
     hint: Label,
     pub pause_btn: LabeledButton,
+    history: HistoryPanel,
+    /// Position after the move picked in the history, shown read-only instead of the live game
+    review: Option<FlowServer>,
+    live_btn: LabeledButton,
+    /// Connection to the host of a networked game, moves are applied once the host accepts them
+    client: Option<Client>,
+    /// Some seat of the networked game is still free
+    waiting: bool,
+    /// Move of the AI searching on a worker thread, dropped with the scene if the human leaves
+    thinking: Option<oneshot::Receiver<Result<FlowCommand, OperationError>>>,
     next_scene: Option<NextScene>,
 }
 
@@ -339,25 +434,40 @@ impl GameScene {
         self.current_player().color()
     }
 
-    pub fn new(mut players: Vec<Player>) -> Self {
-        players.shuffle(&mut rand::rng());
+    pub fn new(mut players: Vec<Player>, setup: &GameSetup) -> Result<Self, InitError> {
+        if setup.shuffle {
+            players.shuffle(&mut rand::rng());
+        }
         let player_count = players.len() as u8;
-        let size = FlowServer::optimal_size(player_count);
-        let game_server = FlowServer::new(FlowServerConfig { player_count, size });
-        let board = BoardComponent {
-            cells: game_server.board.get_cells().clone(),
-            flow_btns: [
-                vec![false; size as usize],
-                vec![false; size as usize],
-                vec![false; size as usize],
-                vec![false; size as usize],
-            ],
-            color_map: players.clone(),
+        let optimal = FlowServer::optimal_size(player_count);
+        let config = |size| FlowServerConfig::new(player_count, size).with_rules(setup.rules);
+        // A size too small for the players falls back to the optimal one
+        let game_server = FlowServer::new(config(setup.board_size.unwrap_or(optimal)))
+            .or_else(|_| FlowServer::new(config(optimal)))?;
+        Ok(Self::with_server(players, game_server))
+    }
 
-            touch_scaling: (1.0, 1.0),
-            touch_state: None,
-            triggered_grid: None,
-        };
+    /// Networked game, the local player takes the seat of the client and every other seat is
+    /// remote, a spectator has no seat
+    pub fn join(client: Client, game_server: FlowServer) -> Self {
+        let players = (0..game_server.player_count())
+            .map(|id| {
+                let color = Player::POSSIBLE_COLORS[id as usize % Player::POSSIBLE_COLORS.len()];
+                if Some(id) == client.seat {
+                    Player::Human(color)
+                } else {
+                    Player::Remote(color)
+                }
+            })
+            .collect();
+        let mut scene = Self::with_server(players, game_server);
+        scene.client = Some(client);
+        scene.waiting = true;
+        scene
+    }
+
+    fn with_server(players: Vec<Player>, game_server: FlowServer) -> Self {
+        let board = BoardComponent::new(players.clone(), &game_server);
         Self {
             players,
             game_server,
@@ -368,79 +478,190 @@ impl GameScene {
                 .with_line_height(Self::HINT_SIZE)
                 .with_texture_align((1.0, 0.0)),
             pause_btn: LabeledButton::pause_btn(),
+            history: HistoryPanel::new(),
+            review: None,
+            live_btn: LabeledButton::new_with_id(
+                "back-to-live",
+                |l| {
+                    l.with_align(Align::Center)
+                        .with_color(colors::WHITE)
+                        .with_font_size(32.)
+                        .with_line_height(32.)
+                        .with_texture_align((0.5, 0.6))
+                },
+                |b| b.with_color(colors::color_primary()).with_radius(0.5),
+            ),
+            client: None,
+            waiting: false,
+            thinking: None,
             next_scene: None,
         }
     }
+
+    /// Start the search of the AI to move, or apply its move once the worker is done
+    fn think(&mut self, difficulty: Difficulty) {
+        let Some(receiver) = &mut self.thinking else {
+            let player = self.game_server.current_player;
+            let mut server = self.game_server.fork();
+            let budget = SearchBudget::time(get_config().ai_think);
+            let (sender, receiver) = oneshot::channel();
+            thread::spawn(move || {
+                let cmd = AI::new(player, difficulty)
+                    .with_budget(budget)
+                    .make_move(&mut server);
+                // Nobody is waiting any more if the scene is gone
+                let _ = sender.send(cmd);
+            });
+            self.thinking = Some(receiver);
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(Ok(cmd)) => {
+                if let Err(e) = self.game_server.handle(cmd) {
+                    println!("Error handling AI command: {:?}", e);
+                }
+            }
+            Ok(Err(e)) => println!("AI found no move: {:?}", e),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Closed) => println!("AI worker stopped"),
+        }
+        self.thinking = None;
+    }
+
+    /// Review the position right after the move at `idx`
+    fn review(&mut self, idx: usize) {
+        let mut server = self.game_server.clone();
+        while server.commands().len() > idx + 1 {
+            server.undo();
+        }
+        self.history.selected = Some(idx);
+        self.review = Some(server);
+    }
+
+    /// Apply what the host sent since the last frame
+    fn poll_host(&mut self) {
+        let Some(client) = &mut self.client else {
+            return;
+        };
+        loop {
+            let message = match client.try_recv() {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Disconnected from host: {}", e);
+                    self.next_scene = Some(NextScene::Pop);
+                    self.client = None;
+                    return;
+                }
+            };
+            match message {
+                ServerMessage::Moved { zobrist, cmd } => {
+                    let res = self.game_server.handle(cmd);
+                    if res.is_err() || self.game_server.board.zobrist() != zobrist {
+                        let _ = client.send(&ClientMessage::Sync);
+                    }
+                }
+                ServerMessage::Sync(record) => match record.replay() {
+                    Ok(server) => self.game_server = server,
+                    Err(e) => println!("Invalid game from host: {}", e),
+                },
+                ServerMessage::Turn(_) => self.waiting = false,
+                ServerMessage::Left(_) => self.waiting = true,
+                ServerMessage::Error(e) => println!("Error handling command: {}", e),
+                ServerMessage::Welcome { .. }
+                | ServerMessage::Joined(_)
+                | ServerMessage::Over(_) => {}
+            }
+        }
+    }
 }
 
 impl Layout for GameScene {
     fn before_render(&mut self, _: &Transform, _: &mut Window) {
-        self.hint.color = self.current_player_color();
-        if self.hint.text != tl!("your-turn") {
-            self.hint.text = tl!("your-turn").into_owned();
-        }
-        self.board.cells = self.game_server.board.get_cells().clone();
-        let len = self.board.board_length() as u8;
-        self.board.flow_btns = [
-            (0..len)
-                .map(|x| {
-                    self.game_server.can_flow_y(x)
-                        && !self.game_server.will_be_recurrence(x, false, false)
-                })
-                .collect(),
-            (0..len)
-                .map(|y| {
-                    self.game_server.can_flow_x(y)
-                        && !self.game_server.will_be_recurrence(y, true, true)
-                })
-                .collect(),
-            (0..len)
-                .map(|y| {
-                    self.game_server.can_flow_x(y)
-                        && !self.game_server.will_be_recurrence(y, true, false)
-                })
-                .collect(),
-            (0..len)
-                .map(|x| {
-                    self.game_server.can_flow_y(x)
-                        && !self.game_server.will_be_recurrence(x, false, true)
-                })
-                .collect(),
-        ];
+        self.poll_host();
         if let Player::AI(_, diff) = self.current_player() {
-            // TODO: make this async...
-            let cmd = AI::new(self.game_server.current_player, diff)
-                .make_move(&mut self.game_server)
-                .unwrap();
-            self.game_server.handle(cmd).unwrap();
+            self.think(diff);
+        }
+        let player_colors: Vec<_> = self.players.iter().map(Player::color).collect();
+        self.history
+            .sync(self.game_server.commands(), &player_colors);
+        if let Some(idx) = self.history.clicked() {
+            self.review(idx);
+        } else if self.history.selected.is_none() {
+            // The host may take back the reviewed move with a sync
+            self.review = None;
+        }
+
+        self.hint.color = self.current_player_color();
+        let hint = if let Some(idx) = self.history.selected {
+            self.hint.color = player_colors[self.game_server.commands()[idx].player() as usize];
+            tl!("reviewing")
+        } else if self.waiting {
+            tl!("waiting-players")
+        } else if self.thinking.is_some() {
+            tl!("thinking")
+        } else {
+            tl!("your-turn")
+        };
+        if self.hint.text != hint {
+            self.hint.text = hint.into_owned();
+        }
+        match &self.review {
+            Some(server) => self.board.show(server, false),
+            None => self.board.show(&self.game_server, true),
         }
     }
 
     fn components(&mut self) -> Vec<(Transform, &mut dyn Component)> {
-        LayoutBuilder::new()
+        let builder = LayoutBuilder::new()
             .at_rect(super::BACK_BTN_RECT, &mut self.pause_btn)
             .at_rect((0.0, 0.0, 0.9, 0.7), &mut self.board)
             .at_rect((0.45, 0.45, 0.5, 0.5), &mut self.hint)
-            .build()
+            .at_rect((-0.37, -0.03, 0.2, 0.7), &mut self.history);
+        if self.review.is_some() {
+            builder.at_rect((-0.37, -0.44, 0.2, 0.07), &mut self.live_btn)
+        } else {
+            builder
+        }
+        .build()
     }
 
     fn after_render(&mut self, _: &Transform, _: &mut Window) {
         if self.pause_btn.triggered() {
             self.next_scene = Some(NextScene::Push(Box::new(PauseScene::default())));
         }
+        if self.live_btn.triggered() {
+            self.review = None;
+            self.history.selected = None;
+        }
         if let Some(g) = self.board.triggered_grid {
             self.board.triggered_grid = None;
-            if self.current_player().is_human() {
+            if self.current_player().is_human() && self.review.is_none() {
                 let cmd = g.to_cmd(self.game_server.phase, self.game_server.current_player);
-                let res = self.game_server.handle(cmd);
-                if let Err(e) = res {
+                if let Some(client) = &mut self.client {
+                    if let Err(e) = client.send(&ClientMessage::Move(cmd)) {
+                        println!("Error sending command: {}", e);
+                    }
+                } else if let Err(e) = self.game_server.handle(cmd) {
                     println!("Error handling command: {:?}", e);
                 }
             }
         }
         if let Some(winner) = self.game_server.winning() {
+            let seats = self
+                .players
+                .iter()
+                .map(|p| {
+                    p.seat()
+                        .map_or("remote".to_string(), |seat| seat.to_string())
+                })
+                .collect();
+            let record = GameRecord::from_server(&self.game_server, seats);
+            save_last_game(&record);
             self.next_scene = Some(NextScene::Replace(Box::new(WinScene::new(
                 self.players[winner as usize].color(),
+                record,
+                self.players.clone(),
             ))));
         }
     }
@@ -1,6 +1,7 @@
 pub mod game;
 pub mod pause;
 pub mod preflight;
+pub mod replay;
 pub mod setting;
 pub mod startup;
 pub mod win;
@@ -1,20 +1,31 @@
+use std::time::{Duration, Instant};
+
 use comui::{
     component::Component,
-    components::{DataComponent, label::Align},
+    components::{
+        DataComponent,
+        label::{Align, Label},
+    },
     layout::{Layout, LayoutBuilder},
     scene::{NextScene, Scene},
     utils::Transform,
 };
-use macroquad::color::Color;
+use macroquad::{color::Color, prelude::info};
 use underflow_ai::Difficulty;
+use underflow_core::{
+    FlowMode,
+    rules::{FillingOrder, Rules},
+};
 
 use crate::{
     colors,
     components::{
         button::{CancelButton, LabeledButton},
+        data_bar::DataBar,
         rounded_rect::RoundedRect,
         single_choice::SingleChoice,
     },
+    config::{GameSetup, Preset, Seat, get_config, update_config},
     scenes::game::GameScene,
     tl,
 };
@@ -23,20 +34,39 @@ use crate::{
 pub enum Player {
     Human(Color),
     AI(Color, Difficulty),
+    /// Seat of another client of a networked game
+    Remote(Color),
 }
 
 impl Player {
-    const POSSIBLE_COLORS: [Color; 4] = [
+    pub const POSSIBLE_COLORS: [Color; 4] = [
         colors::rgb(157, 0, 0),
         colors::rgb(3, 103, 0),
         colors::rgb(0, 34, 127),
         colors::rgb(135, 81, 0),
     ];
 
+    pub fn new(seat: Seat, color: Color) -> Self {
+        match seat {
+            Seat::Human => Player::Human(color),
+            Seat::AI(difficulty) => Player::AI(color, difficulty),
+        }
+    }
+
+    /// Remote players have no local setup
+    pub fn seat(&self) -> Option<Seat> {
+        match self {
+            Player::Human(_) => Some(Seat::Human),
+            Player::AI(_, difficulty) => Some(Seat::AI(*difficulty)),
+            Player::Remote(_) => None,
+        }
+    }
+
     pub fn color(&self) -> Color {
         match self {
             Player::Human(c) => *c,
             Player::AI(c, _) => *c,
+            Player::Remote(c) => *c,
         }
     }
 
@@ -47,7 +77,9 @@ impl Player {
                 Difficulty::Easy => id == tl!("ai-easy"),
                 Difficulty::Medium => id == tl!("ai-medium"),
                 Difficulty::Hard => id == tl!("ai-hard"),
+                Difficulty::Mcts => id == tl!("ai-mcts"),
             },
+            Player::Remote(_) => false,
         }
     }
 
@@ -67,7 +99,7 @@ impl PlayerCard {
     const CANCEL_BTN_MARGIN: f32 = 0.1;
 
     const FONT_SIZE: f32 = 36.0;
-    const PLAYER_OPTIONS: [&str; 4] = ["player", "ai-easy", "ai-medium", "ai-hard"];
+    const PLAYER_OPTIONS: [&str; 5] = ["player", "ai-easy", "ai-medium", "ai-hard", "ai-mcts"];
 
     pub fn new(player: Player) -> Self {
         Self {
@@ -85,7 +117,10 @@ impl PlayerCard {
                     .iter()
                     .map(|k| tl!(*k).into_owned())
                     .collect(),
-                0,
+                Self::PLAYER_OPTIONS
+                    .iter()
+                    .position(|k| player.match_id(&tl!(*k)))
+                    .unwrap_or(0),
                 |l| {
                     l.with_color(player.color().with_alpha(0.8))
                         .with_font_size(Self::FONT_SIZE)
@@ -122,6 +157,8 @@ impl Layout for PlayerCard {
                 self.player = Player::AI(self.player.color(), Difficulty::Medium);
             } else if id == tl!("ai-hard") {
                 self.player = Player::AI(self.player.color(), Difficulty::Hard);
+            } else if id == tl!("ai-mcts") {
+                self.player = Player::AI(self.player.color(), Difficulty::Mcts);
             } else {
                 unreachable!();
             }
@@ -144,18 +181,151 @@ impl Layout for PlayerCard {
     }
 }
 
+/// Board size and rules of the game, every bar cycles through its choices when clicked
+struct GameOptions {
+    size: DataBar<String, SingleChoice>,
+    order: DataBar<String, SingleChoice>,
+    flow: DataBar<String, SingleChoice>,
+    anchors: DataBar<String, SingleChoice>,
+    recurrence: DataBar<String, SingleChoice>,
+    filling: DataBar<String, SingleChoice>,
+    dead_anchors: DataBar<String, SingleChoice>,
+    stuck: DataBar<String, SingleChoice>,
+}
+
+impl GameOptions {
+    const FONT_SIZE: f32 = 30.0;
+    /// Choices that are not numbers are l10n ids
+    const SIZES: [&str; 8] = ["size-auto", "4", "5", "6", "7", "8", "9", "10"];
+
+    fn bar(name: &str, choices: &[&str], selected: usize) -> DataBar<String, SingleChoice> {
+        let choices = choices
+            .iter()
+            .map(|c| match c.parse::<u8>() {
+                Ok(_) => c.to_string(),
+                Err(_) => tl!(*c).into_owned(),
+            })
+            .collect();
+        let mut bar = DataBar::new(
+            tl!(name).into_owned(),
+            SingleChoice::new(choices, selected, |l| {
+                l.with_align(Align::Center)
+                    .with_font_size(Self::FONT_SIZE)
+                    .with_line_height(Self::FONT_SIZE)
+                    .with_color(colors::BLACK)
+                    .with_texture_align((0.5, 0.6))
+            }),
+        );
+        bar.name.font_size = Self::FONT_SIZE;
+        bar
+    }
+
+    fn new(setup: &GameSetup) -> Self {
+        let rules = &setup.rules;
+        let size = setup.board_size.map_or(0, |size| {
+            Self::SIZES
+                .iter()
+                .position(|s| *s == size.to_string())
+                .unwrap_or(0)
+        });
+        Self {
+            size: Self::bar("board-size", &Self::SIZES, size),
+            order: Self::bar(
+                "turn-order",
+                &["order-random", "order-fixed"],
+                (!setup.shuffle) as usize,
+            ),
+            flow: Self::bar(
+                "flow-mode",
+                &["flow-push", "flow-wrap"],
+                (rules.flow == FlowMode::Wrap) as usize,
+            ),
+            anchors: Self::bar(
+                "anchors",
+                &["1", "2", "3"],
+                rules.anchors_per_player.clamp(1, 3) as usize - 1,
+            ),
+            recurrence: Self::bar(
+                "recurrence",
+                &["0", "1", "2"],
+                rules.recurrence_limit.min(2) as usize,
+            ),
+            filling: Self::bar(
+                "filling-order",
+                &["filling-reverse", "filling-forward"],
+                (rules.filling_order == FillingOrder::Forward) as usize,
+            ),
+            dead_anchors: Self::bar(
+                "dead-anchors",
+                &["dead-anchors-clear", "dead-anchors-keep"],
+                (!rules.clear_dead_anchors) as usize,
+            ),
+            stuck: Self::bar(
+                "stuck-players",
+                &["stuck-ignore", "stuck-eliminate"],
+                rules.eliminate_stuck as usize,
+            ),
+        }
+    }
+
+    fn bars(&mut self) -> [&mut DataBar<String, SingleChoice>; 8] {
+        [
+            &mut self.size,
+            &mut self.order,
+            &mut self.flow,
+            &mut self.anchors,
+            &mut self.recurrence,
+            &mut self.filling,
+            &mut self.dead_anchors,
+            &mut self.stuck,
+        ]
+    }
+
+    fn setup(&self, players: Vec<Seat>) -> GameSetup {
+        GameSetup {
+            players,
+            board_size: Self::SIZES[self.size.data.selected()].parse().ok(),
+            shuffle: self.order.data.selected() == 0,
+            rules: Rules {
+                flow: match self.flow.data.selected() {
+                    0 => FlowMode::Push,
+                    _ => FlowMode::Wrap,
+                },
+                anchors_per_player: self.anchors.data.selected() as u8 + 1,
+                recurrence_limit: self.recurrence.data.selected() as u8,
+                filling_order: match self.filling.data.selected() {
+                    0 => FillingOrder::Reverse,
+                    _ => FillingOrder::Forward,
+                },
+                clear_dead_anchors: self.dead_anchors.data.selected() == 0,
+                eliminate_stuck: self.stuck.data.selected() == 1,
+            },
+        }
+    }
+}
+
 pub struct PreflightScene {
     back_btn: LabeledButton,
     pub players: Vec<PlayerCard>,
     add_player_btn: LabeledButton,
+    options: GameOptions,
+    save_preset_btn: LabeledButton,
+    /// One click starts a game with the preset
+    preset_btns: Vec<(LabeledButton, GameSetup)>,
     ready_btn: LabeledButton,
+    /// Why the game did not start, hidden again after a while
+    notice: Label,
+    notice_until: Option<Instant>,
 }
 
 impl PreflightScene {
-    const MAX_PLAYERS: usize = 4;
-    const MIN_PLAYERS: usize = 2;
+    const MAX_PLAYERS: usize = GameSetup::MAX_PLAYERS;
+    const MIN_PLAYERS: usize = GameSetup::MIN_PLAYERS;
 
     const READY_FONT_SIZE: f32 = 64.0;
+    const PRESET_FONT_SIZE: f32 = 28.0;
+    const NOTICE_SIZE: f32 = 36.0;
+    const NOTICE_TIME: Duration = Duration::from_secs(3);
 
     const PLAYER_CARD_WIDTH: f32 = 0.15;
     const PLAYER_CARD_COL_COUNT: u8 = 4;
@@ -169,17 +339,59 @@ impl PreflightScene {
     pub fn new_player_color(&self) -> Color {
         *Player::POSSIBLE_COLORS
             .iter()
-            .find(|&&color| {
-                !self.players.iter().any(|p| match p.player {
-                    Player::Human(c) | Player::AI(c, _) => c == color,
-                })
-            })
+            .find(|&&color| !self.players.iter().any(|p| p.player.color() == color))
             .unwrap()
     }
+
+    fn preset_btns(presets: &[Preset]) -> Vec<(LabeledButton, GameSetup)> {
+        presets
+            .iter()
+            .map(|preset| {
+                let btn = LabeledButton::new_with_text(
+                    &preset.name,
+                    |l| {
+                        l.with_align(Align::Center)
+                            .with_color(colors::WHITE)
+                            .with_font_size(Self::PRESET_FONT_SIZE)
+                            .with_line_height(Self::PRESET_FONT_SIZE)
+                            .with_texture_align((0.5, 0.6))
+                    },
+                    |b| b.with_color(colors::color_secondary()).with_radius(0.5),
+                );
+                (btn, preset.setup.clone())
+            })
+            .collect()
+    }
+
+    fn seats(&self) -> Vec<Seat> {
+        self.players
+            .iter()
+            .filter_map(|card| card.player.seat())
+            .collect()
+    }
+
+    /// Remember the setup as the last one played and start the game, a setup that cannot be
+    /// played stays on this screen with a notice
+    fn start(&mut self, players: Vec<Player>, setup: GameSetup) -> Option<NextScene> {
+        match GameScene::new(players, &setup) {
+            Ok(scene) => {
+                update_config(|config| config.setup = setup);
+                Some(NextScene::Replace(Box::new(scene) as Box<dyn Scene>))
+            }
+            Err(e) => {
+                info!("Cannot start the game: {}", e);
+                self.notice.text = tl!("game-not-started").into_owned();
+                self.notice_until = Some(Instant::now() + Self::NOTICE_TIME);
+                None
+            }
+        }
+    }
 }
 
 impl Default for PreflightScene {
     fn default() -> Self {
+        // Read once, a second read lock on the same thread may deadlock
+        let config = get_config();
         let mut res = Self {
             back_btn: LabeledButton::back_btn(),
             ready_btn: LabeledButton::new_with_id(
@@ -208,21 +420,53 @@ impl Default for PreflightScene {
                 },
             ),
 
+            options: GameOptions::new(&config.setup),
+            save_preset_btn: LabeledButton::new_with_id(
+                "save-preset",
+                |l| {
+                    l.with_align(Align::Center)
+                        .with_color(colors::WHITE)
+                        .with_font_size(Self::PRESET_FONT_SIZE)
+                        .with_line_height(Self::PRESET_FONT_SIZE)
+                        .with_texture_align((0.5, 0.6))
+                },
+                |b| b.with_color(colors::color_secondary()).with_radius(0.5),
+            ),
+            preset_btns: Self::preset_btns(&config.presets),
+            notice: Label::new("")
+                .with_align(Align::Center)
+                .with_color(colors::BLACK)
+                .with_font_size(Self::NOTICE_SIZE),
+            notice_until: None,
+
             players: vec![],
         };
-        res.players
-            .push(PlayerCard::new(Player::Human(res.new_player_color())));
-        res.players
-            .push(PlayerCard::new(Player::Human(res.new_player_color())));
+        let mut seats = config.setup.players.clone();
+        drop(config);
+        if !(Self::MIN_PLAYERS..=Self::MAX_PLAYERS).contains(&seats.len()) {
+            seats = vec![Seat::Human; Self::MIN_PLAYERS];
+        }
+        for seat in seats {
+            res.players
+                .push(PlayerCard::new(Player::new(seat, res.new_player_color())));
+        }
         res
     }
 }
 
 impl Layout for PreflightScene {
     fn before_render(&mut self, _: &Transform, _: &mut comui::window::Window) {
+        if self
+            .notice_until
+            .is_some_and(|until| Instant::now() >= until)
+        {
+            self.notice_until = None;
+            self.notice.text.clear();
+        }
         if self.add_player_btn.triggered() && self.players.len() < Self::MAX_PLAYERS {
+            let seat = get_config().new_player;
             self.players
-                .push(PlayerCard::new(Player::Human(self.new_player_color())));
+                .push(PlayerCard::new(Player::new(seat, self.new_player_color())));
         }
 
         let player_count = self.players.len();
@@ -238,6 +482,15 @@ impl Layout for PreflightScene {
                 self.players.remove(idx);
             }
         }
+
+        for bar in self.options.bars() {
+            bar.data.updated();
+        }
+        if self.save_preset_btn.triggered() {
+            let setup = self.options.setup(self.seats());
+            update_config(|config| config.add_preset(setup));
+            self.preset_btns = Self::preset_btns(&get_config().presets);
+        }
     }
 
     fn components(&mut self) -> Vec<(Transform, &mut dyn Component)> {
@@ -254,7 +507,33 @@ impl Layout for PreflightScene {
         }
         let builder = LayoutBuilder::new()
             .at_rect(super::BACK_BTN_RECT, &mut self.back_btn)
-            .at_rect((0.2, -0.35, 0.5, 0.15), &mut self.ready_btn);
+            .at_rect((-0.3, -0.35, 0.3, 0.12), &mut self.save_preset_btn)
+            .at_rect((0.2, -0.35, 0.5, 0.15), &mut self.ready_btn)
+            .at_rect((0.0, -0.475, 0.8, 0.04), &mut self.notice);
+        let builder =
+            self.preset_btns
+                .iter_mut()
+                .enumerate()
+                .fold(builder, |builder, (idx, (btn, _))| {
+                    builder.at_rect((-0.2 + idx as f32 * 0.17, 0.42, 0.16, 0.07), btn)
+                });
+        let builder =
+            self.options
+                .bars()
+                .into_iter()
+                .enumerate()
+                .fold(builder, |builder, (idx, bar)| {
+                    let (col, row) = (idx % 2, idx / 2);
+                    builder.at_rect(
+                        (
+                            -0.22 + col as f32 * 0.44,
+                            0.04 - row as f32 * 0.08,
+                            0.42,
+                            0.065,
+                        ),
+                        bar,
+                    )
+                });
         let mut last_idx = 0;
         let builder = self
             .players
@@ -278,10 +557,20 @@ impl Scene for PreflightScene {
         if self.back_btn.triggered() {
             return Some(NextScene::Pop);
         }
+        if let Some((_, setup)) = self.preset_btns.iter_mut().find(|(btn, _)| btn.triggered()) {
+            let setup = setup.clone();
+            let players = setup
+                .players
+                .iter()
+                .zip(Player::POSSIBLE_COLORS)
+                .map(|(&seat, color)| Player::new(seat, color))
+                .collect();
+            return self.start(players, setup);
+        }
         if self.ready_btn.triggered() {
-            return Some(NextScene::Replace(Box::new(GameScene::new(
-                self.players.iter().map(|p| p.player).collect(),
-            )) as Box<dyn Scene>));
+            let setup = self.options.setup(self.seats());
+            let players = self.players.iter().map(|p| p.player).collect();
+            return self.start(players, setup);
         }
         None
     }
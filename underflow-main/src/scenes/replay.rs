@@ -0,0 +1,243 @@
+use std::time::{Duration, Instant};
+
+use comui::{
+    component::Component,
+    components::label::{Align, Label},
+    layout::{Layout, LayoutBuilder},
+    scene::{NextScene, Scene},
+    utils::Transform,
+    window::Window,
+};
+use underflow_core::{
+    record::{GameRecord, RecordError},
+    server::FlowServer,
+};
+
+use crate::{
+    colors,
+    components::{
+        button::{LabeledButton, RoundedButton},
+        data_bar::DataBar,
+        history::notation,
+        single_choice::SingleChoice,
+        timeline::Timeline,
+    },
+    scenes::{game::BoardComponent, preflight::Player},
+    tl,
+};
+
+/// Steps through the commands of a game record, the board is read-only
+pub struct ReplayScene {
+    record: GameRecord,
+    /// Position after the first `commands().len()` commands of the record
+    server: FlowServer,
+    players: Vec<Player>,
+    board: BoardComponent,
+    move_label: Label,
+
+    back_btn: LabeledButton,
+    first_btn: LabeledButton,
+    prev_btn: LabeledButton,
+    play_btn: LabeledButton,
+    pause_btn: LabeledButton,
+    next_btn: LabeledButton,
+    last_btn: LabeledButton,
+    speed: DataBar<String, SingleChoice>,
+    timeline: Timeline,
+
+    playing: bool,
+    last_step: Instant,
+    next_scene: Option<NextScene>,
+}
+
+impl ReplayScene {
+    /// Time between two moves at 1x
+    const STEP_TIME: Duration = Duration::from_millis(1000);
+    const SPEEDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+    const CONTROL_SIZE: f32 = 40.0;
+    const CONTROLS_Y: f32 = -0.38;
+
+    fn control_btn(text: &str) -> LabeledButton {
+        LabeledButton::new_with_text(text, Self::control_label, Self::control_button)
+    }
+
+    fn control_label(label: Label) -> Label {
+        label
+            .with_align(Align::Center)
+            .with_color(colors::WHITE)
+            .with_font_size(Self::CONTROL_SIZE)
+            .with_line_height(Self::CONTROL_SIZE)
+            .with_texture_align((0.5, 0.6))
+    }
+
+    fn control_button(button: RoundedButton) -> RoundedButton {
+        button
+            .with_color(colors::color_secondary())
+            .with_radius(0.5)
+    }
+
+    /// `players` gives the colour of every seat of the record
+    pub fn new(record: GameRecord, players: Vec<Player>) -> Result<Self, RecordError> {
+        // Every command is known to be legal from here on
+        record.replay()?;
+        let server = record.replay_to(0)?;
+        let board = BoardComponent::new(players.clone(), &server);
+        let mut speed = DataBar::new(
+            tl!("speed").into_owned(),
+            SingleChoice::new(
+                Self::SPEEDS.iter().map(|s| format!("{}x", s)).collect(),
+                1,
+                |l| {
+                    l.with_align(Align::Center)
+                        .with_font_size(30.)
+                        .with_line_height(30.)
+                        .with_color(colors::BLACK)
+                        .with_texture_align((0.5, 0.6))
+                },
+            ),
+        );
+        speed.name.font_size = 30.;
+        Ok(Self {
+            timeline: Timeline::new(record.commands.len()),
+            record,
+            server,
+            players,
+            board,
+            move_label: Label::new("")
+                .with_align(Align::Center)
+                .with_font_size(48.)
+                .with_line_height(48.)
+                .with_texture_align((0.5, 0.6)),
+
+            back_btn: LabeledButton::back_btn(),
+            first_btn: Self::control_btn("|<"),
+            prev_btn: Self::control_btn("<"),
+            play_btn: LabeledButton::new_with_id("play", Self::control_label, |b| {
+                b.with_color(colors::color_primary()).with_radius(0.5)
+            }),
+            pause_btn: LabeledButton::new_with_id("pause", Self::control_label, |b| {
+                b.with_color(colors::color_primary()).with_radius(0.5)
+            }),
+            next_btn: Self::control_btn(">"),
+            last_btn: Self::control_btn(">|"),
+            speed,
+
+            playing: false,
+            last_step: Instant::now(),
+            next_scene: None,
+        })
+    }
+
+    /// Seats take the colours of a local game in order, for records from a file
+    pub fn with_seat_colors(record: GameRecord) -> Result<Self, RecordError> {
+        let players = (0..record.config.player_count as usize)
+            .map(|id| Player::Human(Player::POSSIBLE_COLORS[id % Player::POSSIBLE_COLORS.len()]))
+            .collect();
+        Self::new(record, players)
+    }
+
+    fn position(&self) -> usize {
+        self.server.commands().len()
+    }
+
+    fn seek(&mut self, position: usize) {
+        let position = position.min(self.record.commands.len());
+        while self.position() > position {
+            self.server.undo();
+        }
+        while self.position() < position {
+            let cmd = self.record.commands[self.position()];
+            if let Err(e) = self.server.handle(cmd) {
+                println!("Error replaying command: {:?}", e);
+                break;
+            }
+        }
+        self.timeline.position = self.position();
+    }
+}
+
+impl Layout for ReplayScene {
+    fn before_render(&mut self, _: &Transform, _: &mut Window) {
+        let end = self.record.commands.len();
+        if let Some(position) = self.timeline.seeked() {
+            self.seek(position);
+        }
+        if self.first_btn.triggered() {
+            self.seek(0);
+        }
+        if self.prev_btn.triggered() {
+            self.seek(self.position().saturating_sub(1));
+        }
+        if self.next_btn.triggered() {
+            self.seek(self.position() + 1);
+        }
+        if self.last_btn.triggered() {
+            self.seek(end);
+        }
+        if self.pause_btn.triggered() {
+            self.playing = false;
+        } else if self.play_btn.triggered() {
+            if self.position() == end {
+                self.seek(0);
+            }
+            self.playing = true;
+            self.last_step = Instant::now();
+        }
+        self.speed.data.updated();
+
+        let speed = Self::SPEEDS[self.speed.data.selected()];
+        if self.playing && self.last_step.elapsed() >= Self::STEP_TIME.div_f32(speed) {
+            self.last_step = Instant::now();
+            self.seek(self.position() + 1);
+        }
+        self.playing &= self.position() < end;
+
+        let text = match self.server.commands().last() {
+            Some(cmd) => {
+                self.move_label.color = self.players[cmd.player() as usize].color();
+                format!("{} / {}    {}", self.position(), end, notation(cmd))
+            }
+            None => {
+                self.move_label.color = colors::BLACK;
+                format!("0 / {}", end)
+            }
+        };
+        if self.move_label.text != text {
+            self.move_label.text = text;
+        }
+        self.board.show(&self.server, false);
+    }
+
+    fn components(&mut self) -> Vec<(Transform, &mut dyn Component)> {
+        let y = Self::CONTROLS_Y;
+        let play_btn = if self.playing {
+            &mut self.pause_btn
+        } else {
+            &mut self.play_btn
+        };
+        LayoutBuilder::new()
+            .at_rect(super::BACK_BTN_RECT, &mut self.back_btn)
+            .at_rect((0.0, 0.42, 0.6, 0.08), &mut self.move_label)
+            .at_rect((0.0, 0.07, 0.9, 0.56), &mut self.board)
+            .at_rect((0.0, -0.27, 0.8, 0.05), &mut self.timeline)
+            .at_rect((-0.34, y, 0.08, 0.08), &mut self.first_btn)
+            .at_rect((-0.24, y, 0.08, 0.08), &mut self.prev_btn)
+            .at_rect((-0.11, y, 0.14, 0.08), play_btn)
+            .at_rect((0.02, y, 0.08, 0.08), &mut self.next_btn)
+            .at_rect((0.12, y, 0.08, 0.08), &mut self.last_btn)
+            .at_rect((0.32, y, 0.24, 0.08), &mut self.speed)
+            .build()
+    }
+
+    fn after_render(&mut self, _: &Transform, _: &mut Window) {
+        if self.back_btn.triggered() {
+            self.next_scene = Some(NextScene::Pop);
+        }
+    }
+}
+
+impl Scene for ReplayScene {
+    fn next_scene(&mut self) -> Option<NextScene> {
+        self.next_scene.take()
+    }
+}
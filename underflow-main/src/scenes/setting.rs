@@ -6,12 +6,12 @@ use comui::{
     utils::Transform,
     window::Window,
 };
-use underflow_l10n::{LANG_NAMES, LANGS};
+use underflow_l10n::{LANG_NAMES, LANGS, locale_order};
 
 use crate::{
     colors,
     components::{button::LabeledButton, data_bar::DataBar, single_choice::SingleChoice},
-    config::{get_config, get_config_mut, sync_config},
+    config::{get_config, sync_config, update_config},
     tl,
 };
 
@@ -36,7 +36,7 @@ impl Default for SettingScene {
                         .language
                         .as_ref()
                         .map(|lang| LANGS.iter().position(|l| lang.as_str() == *l).unwrap_or(0))
-                        .unwrap_or(0),
+                        .unwrap_or_else(|| locale_order()[0]),
                     |l| {
                         l.with_align(Align::Center)
                             .with_font_size(Self::DATA_FONT_SIZE)
@@ -68,7 +68,7 @@ impl Layout for SettingScene {
                 .iter()
                 .position(|lang| *lang == self.lang_bar.data.get_data())
                 .unwrap();
-            get_config_mut().language = Some(LANGS[lang_idx].to_string());
+            update_config(|config| config.language = Some(LANGS[lang_idx].to_string()));
             sync_config();
         }
     }
@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use comui::{
     component::Component,
     components::label::{Align, Label},
@@ -5,20 +7,26 @@ use comui::{
     scene::{NextScene, Scene},
     utils::Transform,
 };
-use macroquad::miniquad::window::quit;
+use macroquad::{miniquad::window::quit, prelude::info};
 use nalgebra::Vector2;
 
 use crate::{
     colors,
     components::button::LabeledButton,
-    scenes::{preflight::PreflightScene, setting::SettingScene},
+    config::load_last_game,
+    scenes::{preflight::PreflightScene, replay::ReplayScene, setting::SettingScene},
+    tl,
 };
 
 pub struct StartupScene {
     title: Label,
     start_btn: LabeledButton,
     settings_btn: LabeledButton,
+    replay_btn: LabeledButton,
     quit_btn: LabeledButton,
+    /// Why the last game cannot be replayed, hidden again after a while
+    notice: Label,
+    notice_until: Option<Instant>,
 
     next_scene: Option<NextScene>,
 }
@@ -38,9 +46,24 @@ impl Default for StartupScene {
                 b.with_color(colors::color_secondary()).with_radius(0.5)
             }),
 
+            replay_btn: LabeledButton::new_with_id(
+                "replay-last-game",
+                |l| {
+                    Self::button_text_label(l)
+                        .with_line_height(Self::SMALL_LABEL_SIZE)
+                        .with_font_size(Self::SMALL_LABEL_SIZE)
+                },
+                |b| b.with_color(colors::color_secondary()).with_radius(0.5),
+            ),
+
             quit_btn: LabeledButton::new_with_id("quit", Self::button_text_label, |b| {
                 b.with_color(colors::color_tertiary()).with_radius(0.5)
             }),
+            notice: Label::new("")
+                .with_align(Align::Center)
+                .with_color(colors::BLACK)
+                .with_font_size(Self::NOTICE_SIZE),
+            notice_until: None,
 
             next_scene: None,
         }
@@ -49,11 +72,14 @@ impl Default for StartupScene {
 
 impl StartupScene {
     const BUTTON_WIDTH: f32 = 0.4;
-    const BUTTON_HEIGHT: f32 = 0.125;
-    const BUTTON_GAP: f32 = 0.05;
+    const BUTTON_HEIGHT: f32 = 0.11;
+    const BUTTON_GAP: f32 = 0.04;
 
     const TITLE_SIZE: f32 = 128.0;
     const BUTTON_LABEL_SIZE: f32 = 72.0;
+    const SMALL_LABEL_SIZE: f32 = 48.0;
+    const NOTICE_SIZE: f32 = 36.0;
+    const NOTICE_TIME: Duration = Duration::from_secs(3);
 
     fn button_text_label(label: Label) -> Label {
         label
@@ -63,11 +89,23 @@ impl StartupScene {
             .with_line_height(Self::BUTTON_LABEL_SIZE)
             .with_font_size(Self::BUTTON_LABEL_SIZE)
     }
+
+    fn show_notice(&mut self, text: String) {
+        self.notice.text = text;
+        self.notice_until = Some(Instant::now() + Self::NOTICE_TIME);
+    }
 }
 
 impl Layout for StartupScene {
     fn before_render(&mut self, tr: &Transform, _: &mut comui::window::Window) {
         self.title.area_width = Some(tr.transform_vector(&Vector2::new(1.0, 0.0)).norm());
+        if self
+            .notice_until
+            .is_some_and(|until| Instant::now() >= until)
+        {
+            self.notice_until = None;
+            self.notice.text.clear();
+        }
     }
 
     fn components(&mut self) -> Vec<(Transform, &mut dyn Component)> {
@@ -93,8 +131,18 @@ impl Layout for StartupScene {
                     Self::BUTTON_WIDTH,
                     Self::BUTTON_HEIGHT,
                 ),
+                &mut self.replay_btn,
+            )
+            .at_rect(
+                (
+                    0.0,
+                    0.05 - (Self::BUTTON_GAP + Self::BUTTON_HEIGHT) * 3.0,
+                    Self::BUTTON_WIDTH,
+                    Self::BUTTON_HEIGHT,
+                ),
                 &mut self.quit_btn,
             )
+            .at_rect((0.0, -0.475, 0.8, 0.04), &mut self.notice)
             .build()
     }
 
@@ -112,6 +160,18 @@ impl Layout for StartupScene {
                 Box::new(SettingScene::default()) as Box<dyn Scene>
             ));
         }
+        if self.replay_btn.triggered() {
+            match load_last_game().map(ReplayScene::with_seat_colors) {
+                Some(Ok(scene)) => {
+                    self.next_scene = Some(NextScene::Push(Box::new(scene) as Box<dyn Scene>));
+                }
+                Some(Err(e)) => {
+                    info!("Cannot replay the last game: {}", e);
+                    self.show_notice(tl!("last-game-unreadable").into_owned());
+                }
+                None => self.show_notice(tl!("no-last-game").into_owned()),
+            }
+        }
     }
 }
 
@@ -5,19 +5,28 @@ use comui::{
     scene::{NextScene, Scene},
     utils::Transform,
 };
-use macroquad::color::Color;
+use macroquad::{color::Color, prelude::info};
+use underflow_core::record::GameRecord;
 
-use crate::{colors, components::button::LabeledButton, tl};
+use crate::{
+    colors,
+    components::button::LabeledButton,
+    scenes::{preflight::Player, replay::ReplayScene},
+    tl,
+};
 
 pub struct WinScene {
     winning_label: Label,
     return_btn: LabeledButton,
+    replay_btn: LabeledButton,
+    record: GameRecord,
+    players: Vec<Player>,
 
     next_scene: Option<NextScene>,
 }
 
 impl WinScene {
-    pub fn new(color: Color) -> Self {
+    pub fn new(color: Color, record: GameRecord, players: Vec<Player>) -> Self {
         Self {
             winning_label: Label::new(tl!("you-win"))
                 .with_align(Align::Center)
@@ -34,6 +43,19 @@ impl WinScene {
                 },
                 |b| b.with_color(colors::color_primary()),
             ),
+            replay_btn: LabeledButton::new_with_id(
+                "watch-replay",
+                |l| {
+                    l.with_color(colors::WHITE)
+                        .with_font_size(48.)
+                        .with_line_height(48.)
+                        .with_align(Align::Center)
+                        .with_texture_align((0.5, 0.6))
+                },
+                |b| b.with_color(colors::color_secondary()),
+            ),
+            record,
+            players,
 
             next_scene: None,
         }
@@ -45,6 +67,7 @@ impl Layout for WinScene {
         LayoutBuilder::new()
             .at_rect((0.0, 0.3, 0.9, 0.3), &mut self.winning_label)
             .at_rect((0.0, -0.1, 0.3, 0.1), &mut self.return_btn)
+            .at_rect((0.0, -0.25, 0.3, 0.1), &mut self.replay_btn)
             .build()
     }
 
@@ -52,6 +75,12 @@ impl Layout for WinScene {
         if self.return_btn.triggered() {
             self.next_scene = Some(NextScene::Pop);
         }
+        if self.replay_btn.triggered() {
+            match ReplayScene::new(self.record.clone(), self.players.clone()) {
+                Ok(scene) => self.next_scene = Some(NextScene::Push(Box::new(scene))),
+                Err(e) => info!("Cannot replay the game: {}", e),
+            }
+        }
     }
 }
 
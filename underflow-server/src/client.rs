@@ -0,0 +1,101 @@
+use std::io::{self, BufReader};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use underflow_core::{
+    net::{ClientMessage, ParseMessageError, ServerMessage, send},
+    server::FlowServer,
+};
+
+/// A joined seat or a spectator, messages from the host are read on a background thread and
+/// polled with [Client::try_recv] so a frame loop never blocks on the network
+pub struct Client {
+    stream: TcpStream,
+    messages: Receiver<io::Result<ServerMessage>>,
+    /// None for a spectator
+    pub seat: Option<u8>,
+}
+
+impl Client {
+    /// Connect and join, blocks until the host sent the game so far
+    pub fn join(addr: impl ToSocketAddrs, seat: Option<u8>) -> io::Result<(Self, FlowServer)> {
+        Self::connect(addr, ClientMessage::Join(seat))
+    }
+
+    /// Connect as a spectator, blocks until the host sent the game so far
+    pub fn watch(addr: impl ToSocketAddrs) -> io::Result<(Self, FlowServer)> {
+        Self::connect(addr, ClientMessage::Watch)
+    }
+
+    fn connect(addr: impl ToSocketAddrs, hello: ClientMessage) -> io::Result<(Self, FlowServer)> {
+        let mut stream = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        send(&mut stream, &hello)?;
+        let closed = || io::Error::from(io::ErrorKind::ConnectionAborted);
+        let seat = match hello {
+            ClientMessage::Join(_) => match ServerMessage::read(&mut reader)?.ok_or_else(closed)? {
+                ServerMessage::Welcome { seat, .. } => Some(seat),
+                ServerMessage::Error(e) => return Err(io::Error::other(e)),
+                message => return Err(ParseMessageError(message.to_string()).into()),
+            },
+            _ => None,
+        };
+        let server = match ServerMessage::read(&mut reader)?.ok_or_else(closed)? {
+            ServerMessage::Sync(record) => record
+                .replay()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            message => return Err(ParseMessageError(message.to_string()).into()),
+        };
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                let message = match ServerMessage::read(&mut reader) {
+                    Ok(Some(message)) => Ok(message),
+                    Ok(None) => break,
+                    Err(e) => Err(e),
+                };
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok((
+            Self {
+                stream,
+                messages,
+                seat,
+            },
+            server,
+        ))
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        send(&mut self.stream, message)
+    }
+
+    /// The next message if one arrived, an error once the connection is gone
+    pub fn try_recv(&self) -> io::Result<Option<ServerMessage>> {
+        match self.messages.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+
+    /// Wait for the next message
+    pub fn recv(&self) -> io::Result<ServerMessage> {
+        self.messages
+            .recv()
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionAborted))?
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = send(&mut self.stream, &ClientMessage::Quit);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
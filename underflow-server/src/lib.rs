@@ -0,0 +1,430 @@
+//! Hosts one game over TCP, see [underflow_core::net] for the protocol
+//!
+//! Every connection gets a reader thread that forwards its lines to the game loop, which owns
+//! the [FlowServer] and is the only one writing to the clients, so messages reach every client
+//! in the same order. The writes go through a bounded queue to a writer thread per connection,
+//! so a peer that stops reading is dropped instead of stalling the game. [Client] is the other
+//! end.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufReader};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::thread;
+
+use underflow_core::{
+    net::{ClientMessage, NetError, ServerMessage, read_line, send},
+    protocol::FlowCommand,
+    record::GameRecord,
+    server::FlowServer,
+};
+
+mod client;
+
+pub use client::Client;
+
+enum Event {
+    Connected(usize, TcpStream),
+    Line(usize, String),
+    Closed(usize),
+}
+
+/// Messages queued for a connection before it counts as stalled
+const OUTBOX: usize = 1024;
+
+struct Connection {
+    stream: TcpStream,
+    outbox: SyncSender<String>,
+    seat: Option<u8>,
+}
+
+impl Connection {
+    fn open(stream: TcpStream) -> io::Result<Self> {
+        let mut writer = stream.try_clone()?;
+        let (outbox, lines) = mpsc::sync_channel::<String>(OUTBOX);
+        thread::spawn(move || {
+            for line in lines {
+                if send(&mut writer, &line).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Ok(Self {
+            stream,
+            outbox,
+            seat: None,
+        })
+    }
+
+    /// Queues a message, false when the peer fell too far behind or is gone
+    fn send(&self, message: &ServerMessage) -> bool {
+        self.outbox.try_send(message.to_string()).is_ok()
+    }
+}
+
+pub struct Host {
+    listener: TcpListener,
+    server: FlowServer,
+    connections: BTreeMap<usize, Connection>,
+    /// Connection id of the client in every seat
+    seats: Vec<Option<usize>>,
+    /// Seat labels for [GameRecord::seats], the last peer that took the seat
+    labels: Vec<String>,
+    /// Connections whose outbox overflowed, closed after the current event
+    stalled: Vec<usize>,
+}
+
+impl Host {
+    pub fn bind(addr: impl ToSocketAddrs, server: FlowServer) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            seats: vec![None; server.player_count() as usize],
+            labels: vec!["Open".to_string(); server.player_count() as usize],
+            server,
+            connections: BTreeMap::new(),
+            stalled: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn record(&self) -> GameRecord {
+        GameRecord::from_server(&self.server, self.labels.clone())
+    }
+
+    /// The winner, or none when the player to move cannot move at all
+    fn result(&self) -> Option<Option<u8>> {
+        if let Some(winner) = self.server.winning() {
+            return Some(Some(winner));
+        }
+        (self.server.phase.is_flowing() && !self.server.has_legal_move(self.server.current_player))
+            .then_some(None)
+    }
+
+    /// Serve until the game is over and every client left
+    pub fn run(mut self) -> io::Result<GameRecord> {
+        let (sender, events) = mpsc::channel();
+        let listener = self.listener.try_clone()?;
+        thread::spawn(move || accept(listener, sender));
+        for event in events {
+            match event {
+                Event::Connected(id, stream) => {
+                    if let Ok(connection) = Connection::open(stream) {
+                        self.connections.insert(id, connection);
+                    }
+                }
+                Event::Line(id, line) => self.handle(id, &line),
+                Event::Closed(id) => self.leave(id),
+            }
+            // Leaving broadcasts too, which may stall more connections
+            while let Some(id) = self.stalled.pop() {
+                self.leave(id);
+            }
+            if self.result().is_some() && self.connections.is_empty() {
+                break;
+            }
+        }
+        Ok(self.record())
+    }
+
+    fn reply(&mut self, id: usize, message: ServerMessage) {
+        if let Some(connection) = self.connections.get(&id) {
+            if !connection.send(&message) {
+                self.stalled.push(id);
+            }
+        }
+    }
+
+    fn broadcast(&mut self, message: ServerMessage) {
+        for (&id, connection) in &self.connections {
+            if !connection.send(&message) {
+                self.stalled.push(id);
+            }
+        }
+    }
+
+    fn handle(&mut self, id: usize, line: &str) {
+        let Some(seat) = self.connections.get(&id).map(|c| c.seat) else {
+            return;
+        };
+        let message = match ClientMessage::parse(seat.unwrap_or_default(), line) {
+            Ok(message) => message,
+            Err(e) => return self.reply(id, ServerMessage::Error(NetError::BadRequest(e.0))),
+        };
+        match message {
+            ClientMessage::Join(wanted) => {
+                if let Err(e) = self.join(id, seat, wanted) {
+                    self.reply(id, ServerMessage::Error(e));
+                }
+            }
+            ClientMessage::Watch => {
+                self.reply(id, ServerMessage::Sync(self.record()));
+                if let Some(result) = self.result() {
+                    self.reply(id, ServerMessage::Over(result));
+                } else if self.seats.iter().all(Option::is_some) {
+                    self.reply(id, ServerMessage::Turn(self.server.current_player));
+                }
+            }
+            ClientMessage::Move(cmd) => {
+                if let Err(e) = self.play(seat, cmd) {
+                    self.reply(id, ServerMessage::Error(e));
+                }
+            }
+            ClientMessage::Sync => self.reply(id, ServerMessage::Sync(self.record())),
+            ClientMessage::Quit => self.leave(id),
+        }
+    }
+
+    fn join(&mut self, id: usize, seat: Option<u8>, wanted: Option<u8>) -> Result<(), NetError> {
+        if seat.is_some() {
+            return Err(NetError::NoSeat);
+        }
+        let free = |seat: &u8| self.seats.get(*seat as usize) == Some(&None);
+        let seat = match wanted {
+            Some(seat) => Some(seat).filter(free),
+            None => (0..self.server.player_count()).find(free),
+        }
+        .ok_or(NetError::NoSeat)?;
+
+        let connection = self.connections.get_mut(&id).unwrap();
+        connection.seat = Some(seat);
+        self.seats[seat as usize] = Some(id);
+        if let Ok(peer) = connection.stream.peer_addr() {
+            self.labels[seat as usize] = format!("Remote {}", peer);
+        }
+        self.reply(
+            id,
+            ServerMessage::Welcome {
+                seat,
+                player_count: self.server.player_count(),
+            },
+        );
+        self.reply(id, ServerMessage::Sync(self.record()));
+        for (&other, connection) in &self.connections {
+            if other != id && !connection.send(&ServerMessage::Joined(seat)) {
+                self.stalled.push(other);
+            }
+        }
+        if let Some(result) = self.result() {
+            self.reply(id, ServerMessage::Over(result));
+        } else if self.seats.iter().all(Option::is_some) {
+            self.broadcast(ServerMessage::Turn(self.server.current_player));
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, seat: Option<u8>, cmd: FlowCommand) -> Result<(), NetError> {
+        if seat.is_none() {
+            return Err(NetError::NotSeated);
+        }
+        if self.result().is_some() {
+            return Err(NetError::Finished);
+        }
+        if self.seats.iter().any(Option::is_none) {
+            return Err(NetError::Waiting);
+        }
+        self.server.handle(cmd).map_err(NetError::Rejected)?;
+
+        self.broadcast(ServerMessage::Moved {
+            zobrist: self.server.board.zobrist(),
+            cmd,
+        });
+        match self.result() {
+            Some(result) => self.broadcast(ServerMessage::Over(result)),
+            None => self.broadcast(ServerMessage::Turn(self.server.current_player)),
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, id: usize) {
+        let Some(connection) = self.connections.remove(&id) else {
+            return;
+        };
+        let _ = connection.stream.shutdown(Shutdown::Both);
+        if let Some(seat) = connection.seat {
+            self.seats[seat as usize] = None;
+            self.broadcast(ServerMessage::Left(seat));
+        }
+    }
+}
+
+fn accept(listener: TcpListener, sender: Sender<Event>) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(reader) = stream.try_clone() else {
+            continue;
+        };
+        if sender.send(Event::Connected(id, stream)).is_err() {
+            return;
+        }
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            // A line over the length limit drops the peer like a closed connection
+            while let Ok(Some(line)) = read_line(&mut reader) {
+                if sender.send(Event::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Event::Closed(id));
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, Read, Write};
+    use underflow_core::{
+        net::MAX_LINE,
+        protocol::{FlowError, FlowEvent, GamePhase},
+        server::FlowServerConfig,
+    };
+
+    #[test]
+    fn test_localhost() {
+        let server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        let host = Host::bind("127.0.0.1:0", server).unwrap();
+        let addr = host.local_addr().unwrap();
+        let handle = thread::spawn(move || host.run());
+
+        let (a, mut mirror) = Client::join(addr, None).unwrap();
+        assert_eq!(a.seat, Some(0));
+        assert!(Client::join(addr, Some(0)).is_err());
+        let (b, _) = Client::join(addr, None).unwrap();
+        assert_eq!(b.seat, Some(1));
+        assert_eq!(a.recv().unwrap(), ServerMessage::Joined(1));
+        let mut clients = [a, b];
+        for client in &clients {
+            assert_eq!(client.recv().unwrap(), ServerMessage::Turn(0));
+        }
+        let (spectator, _) = Client::watch(addr).unwrap();
+        assert_eq!(spectator.seat, None);
+        assert_eq!(spectator.recv().unwrap(), ServerMessage::Turn(0));
+        let mut watched = mirror.clone();
+        let events = watched.subscribe();
+
+        let fill = FlowCommand::parse_move(1, "fill 0 0").unwrap();
+        clients[1].send(&ClientMessage::Move(fill)).unwrap();
+        assert_eq!(
+            clients[1].recv().unwrap(),
+            ServerMessage::Error(NetError::Rejected(FlowError::NotYourTurn))
+        );
+
+        // Both seats play their first legal move until the game ends
+        let result = loop {
+            let player = mirror.current_player;
+            let cmd = mirror.legal_moves(player)[0];
+            clients[player as usize]
+                .send(&ClientMessage::Move(cmd))
+                .unwrap();
+            for client in &clients {
+                let ServerMessage::Moved {
+                    zobrist,
+                    cmd: moved,
+                } = client.recv().unwrap()
+                else {
+                    panic!("Expected a move");
+                };
+                assert_eq!(moved, cmd);
+                if client.seat == Some(0) {
+                    mirror.handle(moved).unwrap();
+                    assert_eq!(zobrist, mirror.board.zobrist());
+                }
+            }
+            let ServerMessage::Moved { cmd: moved, .. } = spectator.recv().unwrap() else {
+                panic!("Expected a move");
+            };
+            watched.handle(moved).unwrap();
+            spectator.recv().unwrap();
+            let next = clients.each_ref().map(|client| client.recv().unwrap());
+            assert_eq!(next[0], next[1]);
+            match &next[0] {
+                ServerMessage::Turn(player) => assert_eq!(*player, mirror.current_player),
+                ServerMessage::Over(result) => break *result,
+                message => panic!("Unexpected {}", message),
+            }
+        };
+        assert_eq!(result, mirror.winning());
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(events.contains(&FlowEvent::PhaseChanged(GamePhase::Flowing)));
+        if let Some(winner) = result {
+            assert_eq!(events.last(), Some(&FlowEvent::GameWon(winner)));
+        }
+
+        clients[0].send(&ClientMessage::Sync).unwrap();
+        let ServerMessage::Sync(record) = clients[0].recv().unwrap() else {
+            panic!("Expected a sync");
+        };
+        assert_eq!(record.commands, mirror.commands());
+        clients[0].send(&ClientMessage::Move(fill)).unwrap();
+        assert_eq!(
+            clients[0].recv().unwrap(),
+            ServerMessage::Error(NetError::Finished)
+        );
+
+        drop(clients);
+        drop(spectator);
+        let record = handle.join().unwrap().unwrap();
+        assert!(
+            record
+                .seats
+                .iter()
+                .all(|seat| seat.starts_with("Remote 127.0.0.1"))
+        );
+        assert_eq!(record.commands, mirror.commands());
+    }
+
+    #[test]
+    fn test_long_line() {
+        let server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        let host = Host::bind("127.0.0.1:0", server).unwrap();
+        let addr = host.local_addr().unwrap();
+        thread::spawn(move || host.run());
+
+        // A peer that never ends its line is dropped instead of buffered
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(&vec![b'x'; MAX_LINE + 1]).unwrap();
+        let mut rest = String::new();
+        assert_eq!(BufReader::new(stream).read_line(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_stalled_reader() {
+        let server = FlowServer::new(FlowServerConfig::new(2, 4)).unwrap();
+        let host = Host::bind("127.0.0.1:0", server).unwrap();
+        let addr = host.local_addr().unwrap();
+        thread::spawn(move || host.run());
+
+        // A spectator asks for far more than fits in its socket and never reads the answers
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"watch\n").unwrap();
+        // The host may hang up before all of it is sent
+        let _ = stream.write_all("sync\n".repeat(100_000).as_bytes());
+
+        let (mut a, _) = Client::join(addr, None).unwrap();
+        let (b, _) = Client::join(addr, None).unwrap();
+        assert_eq!(a.recv().unwrap(), ServerMessage::Joined(1));
+        for client in [&a, &b] {
+            assert_eq!(client.recv().unwrap(), ServerMessage::Turn(0));
+        }
+        let fill = FlowCommand::parse_move(0, "fill 0 0").unwrap();
+        a.send(&ClientMessage::Move(fill)).unwrap();
+        for client in [&a, &b] {
+            let ServerMessage::Moved { cmd, .. } = client.recv().unwrap() else {
+                panic!("Expected a move");
+            };
+            assert_eq!(cmd, fill);
+        }
+
+        // The host gave up on the spectator and closed its connection, with a reset when some
+        // syncs were still unread
+        let mut buf = [0; 4096];
+        while matches!(stream.read(&mut buf), Ok(n) if n > 0) {}
+    }
+}
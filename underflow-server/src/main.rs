@@ -0,0 +1,96 @@
+//! Hosts one game for `underflow-main --join <host>:<port>` or any client speaking the protocol
+//!
+//! ```text
+//! underflow-server [--host 0.0.0.0] [--port 7270] [--players 2] [--size 6] [--rule anchors=2] [--wrap] [--save game.txt]
+//! ```
+
+use anyhow::{Result, anyhow, bail};
+use underflow_core::{
+    net::DEFAULT_PORT,
+    rules::Rules,
+    server::{FlowServer, FlowServerConfig},
+};
+use underflow_server::Host;
+
+const USAGE: &str = "\
+Usage:
+  underflow-server [options]
+
+Options:
+  --host <addr>         Address to listen on (default: 0.0.0.0)
+  --port <n>            Port to listen on (default: 7270)
+  --players <n>         Player count (default: 2)
+  --size <n>            Square board size (default depends on the player count)
+  --rule <key>=<value>  Toggle a house rule, e.g. anchors=2, recurrence=1, filling=forward
+  --wrap                Wrap-around flow variant
+  --save <path>         Write the game record when the game ends";
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid value `{}` for {}", value, flag))
+}
+
+fn main() -> Result<()> {
+    let mut host = "0.0.0.0".to_string();
+    let mut port = DEFAULT_PORT;
+    let mut player_count = 2;
+    let mut size = None;
+    let mut wrap = false;
+    let mut house = Vec::new();
+    let mut save = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => host = parse_value(&arg, args.next())?,
+            "--port" => port = parse_value(&arg, args.next())?,
+            "--players" => player_count = parse_value(&arg, args.next())?,
+            "--size" => size = Some(parse_value::<u8>(&arg, args.next())?),
+            "--rule" => {
+                let value: String = parse_value(&arg, args.next())?;
+                let (key, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Expected <key>=<value>, got `{}`", value))?;
+                house.push((key.to_string(), value.to_string()));
+            }
+            "--wrap" => wrap = true,
+            "--save" => save = Some(parse_value::<String>(&arg, args.next())?),
+            "help" | "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => bail!("Unknown option `{}`\n\n{}", arg, USAGE),
+        }
+    }
+
+    // House rules apply on top of the variant, whichever flag came first
+    let mut rules = if wrap {
+        Rules::wrap()
+    } else {
+        Rules::default()
+    };
+    for (key, value) in house {
+        if !rules.set(&key, &value) {
+            bail!("Unknown rule `{}={}`", key, value);
+        }
+    }
+    let size = size.unwrap_or_else(|| FlowServer::optimal_size(player_count));
+    let server = FlowServer::new(FlowServerConfig::new(player_count, size).with_rules(rules))?;
+    let host = Host::bind((host.as_str(), port), server)?;
+    println!(
+        "Hosting a {}-player game on {}",
+        player_count,
+        host.local_addr()?
+    );
+    let record = host.run()?;
+    match record.replay()?.winning() {
+        Some(winner) => println!("Player {} wins", winner),
+        None => println!("Game over, nobody can move"),
+    }
+    if let Some(path) = save {
+        std::fs::write(&path, record.to_string())?;
+        println!("Saved to {}", path);
+    }
+    Ok(())
+}